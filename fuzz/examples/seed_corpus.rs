@@ -0,0 +1,43 @@
+// Writes a seed corpus for every fuzz target from a test vector file.
+//
+// cargo run --example seed_corpus -- ../tests/data/hand-written-policy-vectors.json
+use oidfed_metadata_policy::conformance::{load_test_vectors, vector_number};
+use serde_json::json;
+
+use std::path::{Path, PathBuf};
+
+fn write_seed(target: &str, name: &str, seed: &serde_json::Value) {
+    let dir = Path::new("corpus").join(target);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(name), serde_json::to_vec(seed).unwrap()).unwrap();
+}
+
+fn main() {
+    let path: PathBuf = std::env::args_os()
+        .nth(1)
+        .expect("Usage: seed_corpus <test vector file>")
+        .into();
+    let vectors = load_test_vectors(&path).unwrap();
+    for (index, vector) in vectors.iter().enumerate() {
+        let n = vector_number(vector).unwrap_or(index as i64 + 1);
+        let name = format!("vector-{}.json", n);
+        let (Some(ta), Some(int)) = (vector.get("TA"), vector.get("INT")) else {
+            continue;
+        };
+        let metadata = vector.get("metadata").cloned().unwrap_or(json!({}));
+        write_seed("merge", &name, &json!({"TA": ta, "INT": int}));
+        write_seed(
+            "chain",
+            &name,
+            &json!({"policies": [ta, int], "metadata": metadata}),
+        );
+        if let Some(merged) = vector.get("merged") {
+            write_seed(
+                "resolve",
+                &name,
+                &json!({"merged": merged, "metadata": metadata}),
+            );
+        }
+    }
+    println!("Wrote seeds for {} vectors", vectors.len());
+}
@@ -0,0 +1,36 @@
+#![no_main]
+// Input: {"policies": [<TA policy>, <intermediate policy>, ...], "metadata": <metadata>}
+//
+// Folds the policies from the trust anchor down, the same way a trust chain is
+// resolved, then applies the result to the leaf metadata.
+use libfuzzer_sys::{fuzz_mutator, fuzz_target};
+use oidfed_metadata_policy::{merge_policies, resolve_metadata_policy};
+use oidfed_metadata_policy_fuzz::{mutate, object};
+use serde_json::{Map, Value};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = serde_json::from_slice::<Value>(data) else {
+        return;
+    };
+    let (Some(policies), Some(metadata)) = (
+        input.get("policies").and_then(Value::as_array),
+        object(input.get("metadata")),
+    ) else {
+        return;
+    };
+    let mut merged = Map::new();
+    for policy in policies.iter() {
+        if !policy.is_object() {
+            return;
+        }
+        match merge_policies(&Value::Object(merged), policy) {
+            Ok(m) => merged = m,
+            Err(_) => return,
+        }
+    }
+    let _ = resolve_metadata_policy(&merged, metadata);
+});
+
+fuzz_mutator!(|data: &mut [u8], size: usize, max_size: usize, seed: u32| {
+    mutate(data, size, max_size, seed)
+});
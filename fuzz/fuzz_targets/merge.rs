@@ -0,0 +1,20 @@
+#![no_main]
+// Input: {"TA": <policy>, "INT": <policy>}
+use libfuzzer_sys::{fuzz_mutator, fuzz_target};
+use oidfed_metadata_policy::merge_policies;
+use oidfed_metadata_policy_fuzz::{mutate, object};
+use serde_json::Value;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = serde_json::from_slice::<Value>(data) else {
+        return;
+    };
+    let (Some(ta), Some(int)) = (object(input.get("TA")), object(input.get("INT"))) else {
+        return;
+    };
+    let _ = merge_policies(&Value::Object(ta.clone()), &Value::Object(int.clone()));
+});
+
+fuzz_mutator!(|data: &mut [u8], size: usize, max_size: usize, seed: u32| {
+    mutate(data, size, max_size, seed)
+});
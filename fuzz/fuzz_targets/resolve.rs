@@ -0,0 +1,22 @@
+#![no_main]
+// Input: {"merged": <policy>, "metadata": <metadata>}
+use libfuzzer_sys::{fuzz_mutator, fuzz_target};
+use oidfed_metadata_policy::resolve_metadata_policy;
+use oidfed_metadata_policy_fuzz::{mutate, object};
+use serde_json::Value;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = serde_json::from_slice::<Value>(data) else {
+        return;
+    };
+    let (Some(policy), Some(metadata)) =
+        (object(input.get("merged")), object(input.get("metadata")))
+    else {
+        return;
+    };
+    let _ = resolve_metadata_policy(policy, metadata);
+});
+
+fuzz_mutator!(|data: &mut [u8], size: usize, max_size: usize, seed: u32| {
+    mutate(data, size, max_size, seed)
+});
@@ -0,0 +1,161 @@
+// Structure-aware mutation of the JSON inputs used by the fuzz targets.
+//
+// The inputs are JSON documents, so the seed corpus can be made from the test vectors.
+// Instead of flipping bytes the mutator changes the parsed document: it swaps in
+// operators, parameter names and values of the wrong type, and falls back to the
+// libFuzzer byte mutations for inputs which are not JSON.
+use serde_json::{Map, Value, json};
+
+const KEYS: [&str; 14] = [
+    "value",
+    "add",
+    "default",
+    "one_of",
+    "subset_of",
+    "superset_of",
+    "essential",
+    "unknown_operator",
+    "grant_types",
+    "token_endpoint_auth_method",
+    "scope",
+    "contacts",
+    "client_name",
+    "",
+];
+
+const STRINGS: [&str; 6] = [
+    "authorization_code",
+    "refresh_token",
+    "private_key_jwt",
+    "openid",
+    "a",
+    "",
+];
+
+// Small xorshift generator, seeded by libFuzzer.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        Rng(u64::from(seed) | 0x9e37_79b9_0000_0000)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+pub fn random_atom(rng: &mut Rng) -> Value {
+    match rng.below(7) {
+        0 => Value::Null,
+        1 => json!(rng.below(2) == 0),
+        2 => json!(rng.below(3) as i64 - 1),
+        3 => json!(0.5),
+        _ => json!(STRINGS[rng.below(STRINGS.len())]),
+    }
+}
+
+pub fn random_value(rng: &mut Rng, depth: usize) -> Value {
+    let choice = if depth == 0 { 0 } else { rng.below(4) };
+    match choice {
+        0 | 1 => random_atom(rng),
+        2 => Value::Array((0..rng.below(4)).map(|_| random_atom(rng)).collect()),
+        _ => {
+            let mut map = Map::new();
+            for _ in 0..rng.below(3) {
+                map.insert(
+                    KEYS[rng.below(KEYS.len())].to_string(),
+                    random_value(rng, depth - 1),
+                );
+            }
+            Value::Object(map)
+        }
+    }
+}
+
+// JSON pointers to every node of the document
+fn collect_pointers(value: &Value, pointer: String, pointers: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter() {
+                let key = key.replace('~', "~0").replace('/', "~1");
+                collect_pointers(child, format!("{}/{}", pointer, key), pointers);
+            }
+        }
+        Value::Array(items) => {
+            for (index, child) in items.iter().enumerate() {
+                collect_pointers(child, format!("{}/{}", pointer, index), pointers);
+            }
+        }
+        _ => (),
+    }
+    pointers.push(pointer);
+}
+
+pub fn mutate_value(document: &mut Value, rng: &mut Rng) {
+    let mut pointers = Vec::new();
+    collect_pointers(document, String::new(), &mut pointers);
+    let pointer = &pointers[rng.below(pointers.len())];
+    let Some(node) = document.pointer_mut(pointer) else {
+        return;
+    };
+    match node {
+        Value::Object(map) => match rng.below(3) {
+            0 if !map.is_empty() => {
+                let key = map.keys().nth(rng.below(map.len())).unwrap().clone();
+                map.remove(&key);
+            }
+            1 => *node = random_value(rng, 2),
+            _ => {
+                map.insert(
+                    KEYS[rng.below(KEYS.len())].to_string(),
+                    random_value(rng, 2),
+                );
+            }
+        },
+        Value::Array(items) => match rng.below(4) {
+            0 if !items.is_empty() => {
+                items.remove(rng.below(items.len()));
+            }
+            1 if !items.is_empty() => {
+                let copy = items[rng.below(items.len())].clone();
+                items.push(copy);
+            }
+            2 => *node = random_value(rng, 2),
+            _ => items.push(random_atom(rng)),
+        },
+        _ => *node = random_value(rng, 1),
+    }
+}
+
+pub fn mutate(data: &mut [u8], size: usize, max_size: usize, seed: u32) -> usize {
+    let mut rng = Rng::new(seed);
+    let Ok(mut document) = serde_json::from_slice::<Value>(&data[..size]) else {
+        return libfuzzer_sys::fuzzer_mutate(data, size, max_size);
+    };
+    // Sometimes mutate the bytes too, to test the parsing of broken documents
+    if rng.below(10) == 0 {
+        return libfuzzer_sys::fuzzer_mutate(data, size, max_size);
+    }
+    for _ in 0..=rng.below(3) {
+        mutate_value(&mut document, &mut rng);
+    }
+    let bytes = serde_json::to_vec(&document).unwrap();
+    if bytes.len() > max_size {
+        return libfuzzer_sys::fuzzer_mutate(data, size, max_size);
+    }
+    data[..bytes.len()].copy_from_slice(&bytes);
+    bytes.len()
+}
+
+// The merge and resolve functions take JSON objects, anything else is not a valid input.
+pub fn object(value: Option<&Value>) -> Option<&Map<String, Value>> {
+    value.and_then(Value::as_object)
+}
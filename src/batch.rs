@@ -0,0 +1,105 @@
+// Resolves every leaf in a directory with the same policy, to see which leaves a new
+// policy breaks before it is published.
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::formats::read_document;
+use crate::policy_file::{PolicyFile, resolve_document};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeafOutcome {
+    // The file name of the leaf
+    pub leaf: String,
+    // The resolved document, or why it could not be resolved
+    pub result: std::result::Result<Value, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchReport {
+    pub outcomes: Vec<LeafOutcome>,
+}
+
+impl BatchReport {
+    pub fn failed(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.result.is_err()).count()
+    }
+}
+
+// A table with a row for each leaf, and the totals
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .outcomes
+            .iter()
+            .map(|o| o.leaf.len())
+            .chain([4])
+            .max()
+            .unwrap();
+        writeln!(f, "{:width$}  {:6}  REASON", "LEAF", "STATUS")?;
+        for outcome in self.outcomes.iter() {
+            let (status, reason) = match &outcome.result {
+                Ok(_) => ("ok", ""),
+                Err(reason) => ("FAILED", reason.as_str()),
+            };
+            let line = format!("{:width$}  {:6}  {}", outcome.leaf, status, reason);
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        let failed = self.failed();
+        write!(
+            f,
+            "\n{} leaves: {} resolved, {} failed",
+            self.outcomes.len(),
+            self.outcomes.len() - failed,
+            failed
+        )
+    }
+}
+
+// The JSON, YAML and TOML files of a directory, sorted by name
+pub fn document_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in
+        std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?
+    {
+        let path = entry?.path();
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if path.is_file() && ["json", "yaml", "yml", "toml"].contains(&extension) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Resolves every document of the leaves directory with the policy. With an output
+// directory the resolved document of each leaf is written to {stem}.resolved.json.
+// A leaf which fails is reported, only reading the directories or writing fails.
+pub fn batch_resolve(
+    policy: &PolicyFile,
+    leaves: &Path,
+    output: Option<&Path>,
+) -> Result<BatchReport> {
+    if let Some(dir) = output {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut report = BatchReport::default();
+    for path in document_files(leaves)? {
+        let leaf = path.file_name().unwrap().to_string_lossy().to_string();
+        let result = read_document(&path).and_then(|leaf| resolve_document(policy, &leaf));
+        if let (Ok(resolved), Some(dir)) = (&result, output) {
+            let stem = path.file_stem().unwrap().to_string_lossy();
+            let target = dir.join(format!("{}.resolved.json", stem));
+            std::fs::write(&target, serde_json::to_string_pretty(resolved)?)
+                .with_context(|| format!("Failed to write {}", target.display()))?;
+        }
+        report.outcomes.push(LeafOutcome {
+            leaf,
+            result: result.map_err(|e| format!("{:#}", e)),
+        });
+    }
+    Ok(report)
+}
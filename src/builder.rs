@@ -0,0 +1,150 @@
+// Builds policies in Rust code instead of nested json! macros:
+//
+//     PolicyBuilder::new()
+//         .entity_type("openid_relying_party")
+//         .param("grant_types")
+//         .subset_of(["authorization_code", "refresh_token"])
+//         .essential(true)
+//         .build()?
+//
+// Every operator is checked against the operators already set on the parameter, the
+// first error is returned by build.
+use anyhow::{Result, anyhow, bail};
+use serde_json::{Map, Value};
+
+use crate::operators::{broken_combination, check_operand};
+
+#[derive(Debug, Default)]
+pub struct PolicyBuilder {
+    // Entity type to parameter to operators, or parameter to operators when no entity
+    // type was given
+    policy: Map<String, Value>,
+    entity_type: Option<String>,
+    parameter: Option<String>,
+    error: Option<anyhow::Error>,
+}
+
+impl PolicyBuilder {
+    pub fn new() -> PolicyBuilder {
+        Default::default()
+    }
+
+    // The following parameters are in the policy for this entity type
+    pub fn entity_type(mut self, entity_type: &str) -> PolicyBuilder {
+        if self.error.is_none() && self.entity_type.is_none() && !self.policy.is_empty() {
+            self.error = Some(anyhow!(
+                "Policy error: {} comes after parameters without an entity type",
+                entity_type
+            ));
+        }
+        self.policy
+            .entry(entity_type)
+            .or_insert_with(|| Value::Object(Map::new()));
+        self.entity_type = Some(entity_type.to_string());
+        self.parameter = None;
+        self
+    }
+
+    // The following operators are for this parameter
+    pub fn param(mut self, parameter: &str) -> PolicyBuilder {
+        let policy = match &self.entity_type {
+            Some(entity_type) => self.policy[entity_type].as_object_mut().unwrap(),
+            None => &mut self.policy,
+        };
+        policy
+            .entry(parameter)
+            .or_insert_with(|| Value::Object(Map::new()));
+        self.parameter = Some(parameter.to_string());
+        self
+    }
+
+    pub fn value(self, value: impl Into<Value>) -> PolicyBuilder {
+        self.operator("value", value.into())
+    }
+
+    // Named after the operator, not std::ops::Add
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<V: Into<Value>>(self, values: impl IntoIterator<Item = V>) -> PolicyBuilder {
+        self.operator("add", to_array(values))
+    }
+
+    pub fn default(self, value: impl Into<Value>) -> PolicyBuilder {
+        self.operator("default", value.into())
+    }
+
+    pub fn one_of<V: Into<Value>>(self, values: impl IntoIterator<Item = V>) -> PolicyBuilder {
+        self.operator("one_of", to_array(values))
+    }
+
+    pub fn subset_of<V: Into<Value>>(self, values: impl IntoIterator<Item = V>) -> PolicyBuilder {
+        self.operator("subset_of", to_array(values))
+    }
+
+    pub fn superset_of<V: Into<Value>>(self, values: impl IntoIterator<Item = V>) -> PolicyBuilder {
+        self.operator("superset_of", to_array(values))
+    }
+
+    pub fn essential(self, essential: bool) -> PolicyBuilder {
+        self.operator("essential", Value::Bool(essential))
+    }
+
+    fn operator(mut self, operator: &str, operand: Value) -> PolicyBuilder {
+        if self.error.is_some() {
+            return self;
+        }
+        let Some(parameter) = self.parameter.clone() else {
+            self.error = Some(anyhow!("Policy error: {} needs a parameter", operator));
+            return self;
+        };
+        if let Err(e) = check_operand(operator, &operand) {
+            self.error = Some(anyhow!("Policy error: {}: {}", parameter, e));
+            return self;
+        }
+        let policy = match &self.entity_type {
+            Some(entity_type) => self.policy[entity_type].as_object_mut().unwrap(),
+            None => &mut self.policy,
+        };
+        let operators = policy[&parameter].as_object_mut().unwrap();
+        if operators.contains_key(operator) {
+            self.error = Some(anyhow!(
+                "Policy error: {}: {} is already set",
+                parameter,
+                operator
+            ));
+            return self;
+        }
+        operators.insert(operator.to_string(), operand);
+        if let Some(rule) = broken_combination(operators) {
+            self.error = Some(anyhow!("Policy error: {}: {}", parameter, rule.message));
+        }
+        self
+    }
+
+    // The policy for one entity type when entity_type was never called, otherwise the
+    // value of a metadata_policy claim.
+    pub fn build(self) -> Result<Value> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let policies: Vec<&Map<String, Value>> = match self.entity_type {
+            Some(_) => self
+                .policy
+                .values()
+                .map(|p| p.as_object().unwrap())
+                .collect(),
+            None => vec![&self.policy],
+        };
+        for policy in policies.into_iter() {
+            for (parameter, operators) in policy.iter() {
+                if operators.as_object().unwrap().is_empty() {
+                    bail!("Policy error: {} has no operators", parameter);
+                }
+            }
+        }
+        Ok(Value::Object(self.policy))
+    }
+}
+
+fn to_array<V: Into<Value>>(values: impl IntoIterator<Item = V>) -> Value {
+    Value::Array(values.into_iter().map(Into::into).collect())
+}
@@ -0,0 +1,485 @@
+// Trust chain validation, OpenID Federation section 10.2. A chain is the entity
+// configuration of the leaf, the subordinate statements from the one about the leaf up
+// to the one issued by the trust anchor, and the entity configuration of the trust
+// anchor.
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value};
+
+use std::collections::HashMap;
+
+use crate::constraints::{check_constraints, strip_entity_types};
+use crate::jws::{Jws, verify_entity_statement};
+use crate::resolve_metadata_policies;
+use crate::statement::{
+    EntityStatement, MetadataOverride, apply_superior_metadata, merge_statement_policies,
+};
+use crate::store::StatementStore;
+use crate::trust_mark::{TrustMark, TrustMarkChecker, TrustMarkOutcome, check_trust_marks};
+
+// The most entities on one path from the leaf, the leaf and the trust anchor included
+pub const MAX_PATH_LENGTH: usize = 10;
+// The most statements one search looks up, each is a fetch through a FetchingStore
+pub const MAX_STATEMENTS: usize = 100;
+// The most authority_hints one search follows, the ones with statements already looked
+// up included
+pub const MAX_HINTS_FOLLOWED: usize = 1000;
+
+// A trust anchor with the keys it is known by, which do not come from the chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrustAnchor {
+    pub entity_id: String,
+    pub jwks: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidatedChain {
+    // In the order of the chain, the leaf first
+    pub statements: Vec<EntityStatement>,
+    // The signed statements, in the same order
+    pub trust_chain: Vec<String>,
+    // The metadata of the leaf with the policies of the chain applied, without the entity
+    // types the constraints do not allow
+    pub metadata: Map<String, Value>,
+    // The merged metadata_policy of the chain
+    pub policy: Map<String, Value>,
+    // The parameters the superior of the leaf set in its statement about the leaf
+    pub overrides: Vec<MetadataOverride>,
+    // The earliest exp of the statements
+    pub expires_at: i64,
+    // The trust marks of the leaf, None until they are checked
+    pub trust_marks: Option<Vec<TrustMarkOutcome>>,
+}
+
+impl ValidatedChain {
+    pub fn leaf(&self) -> &EntityStatement {
+        &self.statements[0]
+    }
+
+    pub fn trust_anchor(&self) -> &EntityStatement {
+        self.statements.last().unwrap()
+    }
+
+    // Checks the trust marks of the leaf, see trust_mark::check_trust_marks.
+    pub fn check_trust_marks(&mut self, store: &dyn StatementStore, now: i64) {
+        self.trust_marks = Some(check_trust_marks(self, store, now));
+    }
+
+    // Checks the trust marks of the leaf with the issuer keys the checker already found.
+    pub fn check_trust_marks_with(&mut self, checker: &TrustMarkChecker) {
+        self.trust_marks = Some(checker.check(self));
+    }
+
+    // The trust marks of the leaf which were checked and are valid
+    pub fn valid_trust_marks(&self) -> Vec<&TrustMark> {
+        self.trust_marks
+            .iter()
+            .flatten()
+            .filter_map(|o| o.result.as_ref().ok())
+            .collect()
+    }
+}
+
+// Validates a chain of signed statements at the time now (seconds since the epoch), and
+// resolves the metadata of the leaf.
+pub fn validate_trust_chain(
+    chain: &[&str],
+    trust_anchor: &TrustAnchor,
+    now: i64,
+) -> Result<ValidatedChain> {
+    if chain.is_empty() {
+        bail!("Trust chain error: the chain is empty");
+    }
+    if chain.len() == 2 {
+        bail!("Trust chain error: there is no subordinate statement about the leaf");
+    }
+
+    // Every statement is signed with a key from the statement after it, the last one with
+    // the keys of the trust anchor
+    let mut statements: Vec<EntityStatement> = Vec::new();
+    let mut keys = &trust_anchor.jwks;
+    for (j, token) in chain.iter().enumerate().rev() {
+        let statement = verify_entity_statement(token, keys)
+            .with_context(|| format!("Trust chain error: statement {} is not valid", j))?;
+        statements.insert(0, statement);
+        keys = &statements[0].jwks;
+    }
+
+    let last = statements.len() - 1;
+    for (j, statement) in statements.iter().enumerate() {
+        let expected = if j == 0 || j == last {
+            "an entity configuration"
+        } else {
+            "a subordinate statement"
+        };
+        if statement.is_configuration() != (j == 0 || j == last) {
+            bail!("Trust chain error: statement {} must be {}", j, expected);
+        }
+        if j < last && statement.iss != statements[j + 1].sub {
+            bail!(
+                "Trust chain error: statement {} is issued by {}, but statement {} is about {}",
+                j,
+                statement.iss,
+                j + 1,
+                statements[j + 1].sub
+            );
+        }
+        if statement.iat > now {
+            bail!("Trust chain error: statement {} is issued in the future", j);
+        }
+        if statement.exp <= now {
+            bail!("Trust chain error: statement {} has expired", j);
+        }
+    }
+    if statements[last].iss != trust_anchor.entity_id {
+        bail!(
+            "Trust chain error: the chain ends at {}, not at the trust anchor {}",
+            statements[last].iss,
+            trust_anchor.entity_id
+        );
+    }
+
+    check_constraints(&statements).context("Trust chain error: the chain breaks a constraint")?;
+
+    // The subordinate statements, from the one by the trust anchor down to the leaf
+    let subordinates: Vec<EntityStatement> = match last {
+        0 => Vec::new(),
+        _ => statements[1..last].iter().rev().cloned().collect(),
+    };
+    let policy = merge_statement_policies(&subordinates)?;
+    let Some(leaf_metadata) = &statements[0].metadata else {
+        bail!("Trust chain error: the leaf has no metadata");
+    };
+    // The metadata set by the superior replaces that of the leaf, then the entity types a
+    // superior does not allow are dropped, and the policies apply last
+    let (leaf_metadata, overrides) = match statements.get(1) {
+        Some(superior) => apply_superior_metadata(leaf_metadata, superior)?,
+        None => (leaf_metadata.clone(), Vec::new()),
+    };
+    let leaf_metadata = strip_entity_types(&statements, &leaf_metadata)?;
+    let metadata = resolve_metadata_policies(&policy, &leaf_metadata)?;
+    let expires_at = statements.iter().map(|s| s.exp).min().unwrap();
+    Ok(ValidatedChain {
+        statements,
+        trust_chain: chain.iter().map(|t| t.trim().to_string()).collect(),
+        metadata,
+        policy,
+        overrides,
+        expires_at,
+        trust_marks: None,
+    })
+}
+
+// The chains found from a leaf to the trust anchors, and why the other paths failed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChainSearch {
+    // Signed statements in the order validate_trust_chain takes them
+    pub chains: Vec<Vec<String>>,
+    // Loops and dead ends
+    pub problems: Vec<String>,
+}
+
+impl ChainSearch {
+    // Validates every chain found to one of the trust anchors.
+    pub fn outcomes(&self, trust_anchors: &[TrustAnchor], now: i64) -> Vec<ChainOutcome> {
+        let mut outcomes = Vec::new();
+        for chain in self.chains.iter() {
+            let tokens: Vec<&str> = chain.iter().map(String::as_str).collect();
+            let path = chain_path(&tokens);
+            // The anchor was found by its entity ID, the keys are checked here
+            let Some(anchor) = trust_anchors
+                .iter()
+                .find(|a| path.last() == Some(&a.entity_id))
+            else {
+                continue;
+            };
+            outcomes.push(ChainOutcome {
+                trust_anchor: anchor.entity_id.clone(),
+                path,
+                result: validate_trust_chain(&tokens, anchor, now).map_err(|e| format!("{:#}", e)),
+            });
+        }
+        outcomes
+    }
+
+    // Validates every chain found, a chain which fails is added to the problems.
+    pub fn validate(
+        &self,
+        trust_anchors: &[TrustAnchor],
+        now: i64,
+    ) -> (Vec<ValidatedChain>, Vec<String>) {
+        let mut valid = Vec::new();
+        let mut problems = self.problems.clone();
+        for outcome in self.outcomes(trust_anchors, now) {
+            match outcome.result {
+                Ok(chain) => valid.push(chain),
+                Err(e) => problems.push(format!("chain to {}: {}", outcome.trust_anchor, e)),
+            }
+        }
+        (valid, problems)
+    }
+
+    // Validates every chain found and picks one of the valid chains.
+    pub fn resolve(
+        &self,
+        trust_anchors: &[TrustAnchor],
+        now: i64,
+        selection: &ChainSelection,
+    ) -> ChainResolution {
+        let outcomes = self.outcomes(trust_anchors, now);
+        let selected = selection.select(&outcomes);
+        ChainResolution {
+            outcomes,
+            problems: self.problems.clone(),
+            selected,
+        }
+    }
+}
+
+// What became of one chain found to a trust anchor
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainOutcome {
+    pub trust_anchor: String,
+    // The entity IDs from the leaf up to the trust anchor
+    pub path: Vec<String>,
+    pub result: std::result::Result<ValidatedChain, String>,
+}
+
+// How to pick one chain when a leaf has valid chains to several trust anchors. Ties go to
+// the chain found first.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainSelection {
+    // The trust anchor listed first, chains to anchors not in the list come last
+    PreferredAnchors(Vec<String>),
+    // The fewest statements
+    ShortestPath,
+    // The earliest expires_at
+    EarliestExpiry,
+}
+
+impl ChainSelection {
+    // The index of the chosen outcome, None when no chain is valid.
+    pub fn select(&self, outcomes: &[ChainOutcome]) -> Option<usize> {
+        let valid = outcomes
+            .iter()
+            .enumerate()
+            .filter_map(|(n, o)| o.result.as_ref().ok().map(|chain| (n, o, chain)));
+        let best = match self {
+            ChainSelection::PreferredAnchors(anchors) => valid.min_by_key(|(_, o, _)| {
+                anchors
+                    .iter()
+                    .position(|a| *a == o.trust_anchor)
+                    .unwrap_or(anchors.len())
+            }),
+            ChainSelection::ShortestPath => {
+                valid.min_by_key(|(_, _, chain)| chain.statements.len())
+            }
+            ChainSelection::EarliestExpiry => valid.min_by_key(|(_, _, chain)| chain.expires_at),
+        };
+        best.map(|(n, _, _)| n)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainResolution {
+    // One for every chain found to a trust anchor
+    pub outcomes: Vec<ChainOutcome>,
+    // The loops and dead ends of the search
+    pub problems: Vec<String>,
+    // The index of the chosen outcome
+    pub selected: Option<usize>,
+}
+
+impl ChainResolution {
+    pub fn selected(&self) -> Option<&ValidatedChain> {
+        let outcome = &self.outcomes[self.selected?];
+        outcome.result.as_ref().ok()
+    }
+}
+
+// The iss of every statement of a chain, without verifying them
+fn chain_path(tokens: &[&str]) -> Vec<String> {
+    let mut path: Vec<String> = tokens
+        .iter()
+        .map(|token| {
+            Jws::parse(token)
+                .ok()
+                .and_then(|j| j.payload["iss"].as_str().map(String::from))
+                .unwrap_or_default()
+        })
+        .collect();
+    // The trust anchor issues both of the last two statements
+    if path.len() > 1 {
+        path.pop();
+    }
+    path
+}
+
+// Follows the authority_hints from the leaf to every trust anchor it can reach with
+// the statements in the store. The statements are not verified here. Paths longer than
+// MAX_PATH_LENGTH are dead ends, and the search fails when it needs more than
+// MAX_STATEMENTS statements or follows more than MAX_HINTS_FOLLOWED authority_hints.
+pub fn build_trust_chains(
+    leaf: &str,
+    trust_anchors: &[TrustAnchor],
+    store: &dyn StatementStore,
+) -> Result<ChainSearch> {
+    let Some(configuration) = store.entity_configuration(leaf)? else {
+        bail!("Trust chain error: no entity configuration for {}", leaf);
+    };
+    let mut search = ChainSearch::default();
+    let mut walk = Walk {
+        trust_anchors,
+        store,
+        search: &mut search,
+        // The leaf counts as a statement of the search too
+        statements: HashMap::from([(
+            (leaf.to_string(), leaf.to_string()),
+            Ok(Some(configuration.clone())),
+        )]),
+        hints_followed: 0,
+    };
+    walk.from(
+        leaf,
+        &configuration,
+        vec![configuration.clone()],
+        vec![leaf],
+    )?;
+    Ok(search)
+}
+
+struct Walk<'a> {
+    trust_anchors: &'a [TrustAnchor],
+    store: &'a dyn StatementStore,
+    search: &'a mut ChainSearch,
+    // (iss, sub) to what the store had, paths which meet again do not look it up again
+    statements: HashMap<(String, String), std::result::Result<Option<String>, String>>,
+    hints_followed: usize,
+}
+
+impl Walk<'_> {
+    // The statement by issuer about subject, from the store the first time. The error
+    // of the store is a dead end, the outer one ends the search.
+    fn statement(
+        &mut self,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<std::result::Result<Option<String>, String>> {
+        let key = (issuer.to_string(), subject.to_string());
+        if let Some(found) = self.statements.get(&key) {
+            return Ok(found.clone());
+        }
+        if self.statements.len() == MAX_STATEMENTS {
+            bail!(
+                "Trust chain error: the search needs more than {} statements",
+                MAX_STATEMENTS
+            );
+        }
+        let found = if issuer == subject {
+            self.store.entity_configuration(issuer)
+        } else {
+            self.store.statement(issuer, subject)
+        };
+        let found = found.map_err(|e| format!("{:#}", e));
+        self.statements.insert(key, found.clone());
+        Ok(found)
+    }
+
+    fn from(
+        &mut self,
+        entity_id: &str,
+        configuration: &str,
+        tokens: Vec<String>,
+        path: Vec<&str>,
+    ) -> Result<()> {
+        let is_anchor = self.trust_anchors.iter().any(|a| a.entity_id == entity_id);
+        if is_anchor {
+            let mut chain = tokens.clone();
+            if path.len() > 1 {
+                chain.push(configuration.to_string());
+            }
+            self.search.chains.push(chain);
+        }
+        let hints = match authority_hints(configuration) {
+            Ok(hints) => hints,
+            Err(e) => {
+                self.search
+                    .problems
+                    .push(format!("dead end at {}: {:#}", path.join(" -> "), e));
+                return Ok(());
+            }
+        };
+        if hints.is_empty() && !is_anchor {
+            self.search.problems.push(format!(
+                "dead end at {}: {} has no authority_hints and is not a trust anchor",
+                path.join(" -> "),
+                entity_id
+            ));
+        }
+        for hint in hints.iter() {
+            if path.contains(&hint.as_str()) {
+                self.search
+                    .problems
+                    .push(format!("loop: {} -> {}", path.join(" -> "), hint));
+                continue;
+            }
+            let dead_end =
+                |reason: String| format!("dead end at {}: {}", path.join(" -> "), reason);
+            if path.len() == MAX_PATH_LENGTH {
+                let reason = format!("the path is already {} entities long", MAX_PATH_LENGTH);
+                self.search.problems.push(dead_end(reason));
+                continue;
+            }
+            self.hints_followed += 1;
+            if self.hints_followed > MAX_HINTS_FOLLOWED {
+                bail!(
+                    "Trust chain error: the search follows more than {} authority_hints",
+                    MAX_HINTS_FOLLOWED
+                );
+            }
+            // An unreachable superior is a dead end, not a failure of the whole search
+            let statement = match self.statement(hint, entity_id)? {
+                Ok(Some(statement)) => statement,
+                Ok(None) => {
+                    let reason = format!("no statement by {} about {}", hint, entity_id);
+                    self.search.problems.push(dead_end(reason));
+                    continue;
+                }
+                Err(e) => {
+                    self.search.problems.push(dead_end(e));
+                    continue;
+                }
+            };
+            let superior = match self.statement(hint, hint)? {
+                Ok(Some(superior)) => superior,
+                Ok(None) => {
+                    let reason = format!("no entity configuration for {}", hint);
+                    self.search.problems.push(dead_end(reason));
+                    continue;
+                }
+                Err(e) => {
+                    self.search.problems.push(dead_end(e));
+                    continue;
+                }
+            };
+            let mut tokens = tokens.clone();
+            tokens.push(statement);
+            let mut path = path.clone();
+            path.push(hint);
+            self.from(hint, &superior, tokens, path)?;
+        }
+        Ok(())
+    }
+}
+
+fn authority_hints(configuration: &str) -> Result<Vec<String>> {
+    let jws = Jws::parse(configuration)?;
+    match jws.payload.get("authority_hints") {
+        None => Ok(Vec::new()),
+        Some(Value::Array(hints)) => Ok(hints
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect()),
+        Some(_) => bail!("Entity statement error: authority_hints has the wrong type"),
+    }
+}
@@ -0,0 +1,293 @@
+// Runner for the connect2id metadata policy test vectors.
+// https://connect2id.com/blog/metadata-policy-test-vectors-openid-federation
+use anyhow::{Context, Result, bail};
+use serde_json::{Value, json};
+
+use std::any::Any;
+use std::ops::RangeInclusive;
+use std::panic;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::{check_equal, merge_policies, resolve_metadata_policy};
+
+pub fn load_test_vectors(path: &Path) -> Result<Vec<Value>> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read test vectors from {}", path.display()))?;
+    let input: Value = serde_json::from_str(&data)
+        .with_context(|| format!("Failed to parse test vectors from {}", path.display()))?;
+    match input {
+        Value::Array(vectors) => Ok(vectors),
+        _ => bail!("Test vector file must contain a JSON array"),
+    }
+}
+
+// The test number of a vector, the `n` member.
+pub fn vector_number(vector: &Value) -> Option<i64> {
+    vector.get("n").and_then(Value::as_i64)
+}
+
+// Which test numbers to run, for example `1458`, `10-20` or `1,5,10-20`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorFilter {
+    ranges: Vec<RangeInclusive<i64>>,
+}
+
+impl VectorFilter {
+    pub fn matches(&self, n: i64) -> bool {
+        self.ranges.iter().any(|r| r.contains(&n))
+    }
+}
+
+impl FromStr for VectorFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut ranges = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            ranges.push(parse_range(part)?);
+        }
+        if ranges.is_empty() {
+            bail!("No test numbers given");
+        }
+        Ok(VectorFilter { ranges })
+    }
+}
+
+// One part of a filter, `1458` or `10-20`
+fn parse_range(part: &str) -> Result<RangeInclusive<i64>> {
+    let number = |n: &str, what: &str| -> Result<i64> {
+        n.trim()
+            .parse()
+            .with_context(|| format!("Invalid {}: {}", what, part))
+    };
+    match part.split_once('-') {
+        Some((start, end)) => {
+            let start = number(start, "start of test number range")?;
+            let end = number(end, "end of test number range")?;
+            if start > end {
+                bail!("Empty test number range: {}", part);
+            }
+            Ok(start..=end)
+        }
+        None => {
+            let n = number(part, "test number")?;
+            Ok(n..=n)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VectorResult {
+    pub n: i64,
+    // None when the vector passed, else why it failed
+    pub failure: Option<String>,
+    pub duration: Duration,
+}
+
+impl VectorResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+// Runs one test vector: merges `TA` and `INT`, compares with `merged`, then applies the
+// merged policy to `metadata` and compares with `resolved`. A vector with an `error`
+// member passes when either step fails.
+pub fn check_test_vector(vector: &Value) -> std::result::Result<(), String> {
+    let Some(input_map) = vector.as_object() else {
+        return Err("Test vector is not a JSON object".to_string());
+    };
+    let expects_error = input_map.contains_key("error");
+    let (Some(ta), Some(int)) = (input_map.get("TA"), input_map.get("INT")) else {
+        return Err("Test vector is missing TA or INT".to_string());
+    };
+    if !ta.is_object() || !int.is_object() {
+        return Err("TA and INT must be JSON objects".to_string());
+    }
+
+    let merged = match merge_policies(ta, int) {
+        Ok(m) => m,
+        Err(e) => {
+            if expects_error {
+                return Ok(());
+            }
+            return Err(format!("Unexpected merge error: {}", e));
+        }
+    };
+    match input_map.get("merged").and_then(Value::as_object) {
+        Some(expected) => {
+            if *expected != merged {
+                return Err(format!(
+                    "Merged policy mismatch: expected {} got {}",
+                    Value::Object(expected.clone()),
+                    Value::Object(merged)
+                ));
+            }
+        }
+        None => {
+            return Err(format!(
+                "Missing merged output, merge gave {}",
+                Value::Object(merged)
+            ));
+        }
+    }
+
+    // Merge worked, now we should apply the input to the merged answer
+    let Some(metadata) = input_map.get("metadata").and_then(Value::as_object) else {
+        return Err("Test vector is missing metadata".to_string());
+    };
+    let result = match resolve_metadata_policy(&merged, metadata) {
+        Ok(r) => r,
+        Err(e) => {
+            if expects_error {
+                return Ok(());
+            }
+            return Err(format!("Unexpected resolve error: {}", e));
+        }
+    };
+    if expects_error {
+        return Err(format!("Expected an error, resolve gave {}", result));
+    }
+    match input_map.get("resolved") {
+        Some(resolved) if resolved.is_object() => {
+            if !check_equal(resolved, &result) {
+                return Err(format!(
+                    "Resolved metadata mismatch: expected {} got {}",
+                    resolved, result
+                ));
+            }
+        }
+        _ => return Err(format!("Missing resolved output, resolve gave {}", result)),
+    }
+    Ok(())
+}
+
+// Runs every vector accepted by the filter, continuing past failures.
+pub fn run_test_vectors(vectors: &[Value], filter: Option<&VectorFilter>) -> Vec<VectorResult> {
+    run_test_vectors_with(vectors, filter, check_test_vector)
+}
+
+// Like run_test_vectors, with another check for each vector.
+pub fn run_test_vectors_with(
+    vectors: &[Value],
+    filter: Option<&VectorFilter>,
+    check: impl Fn(&Value) -> std::result::Result<(), String> + panic::RefUnwindSafe,
+) -> Vec<VectorResult> {
+    let mut results = Vec::new();
+    for (index, vector) in vectors.iter().enumerate() {
+        // Vectors without a number are reported by their position in the file
+        let n = vector_number(vector).unwrap_or(index as i64 + 1);
+        if filter.is_some_and(|f| !f.matches(n)) {
+            continue;
+        }
+        let start = Instant::now();
+        // A panic in one vector should not stop the whole run
+        let failure = match panic::catch_unwind(|| check(vector)) {
+            Ok(outcome) => outcome.err(),
+            Err(cause) => Some(format!("Panicked: {}", panic_message(&cause))),
+        };
+        results.push(VectorResult {
+            n,
+            failure,
+            duration: start.elapsed(),
+        });
+    }
+    results
+}
+
+fn panic_message(cause: &Box<dyn Any + Send>) -> String {
+    if let Some(s) = cause.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = cause.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown cause".to_string()
+    }
+}
+
+pub fn summary(results: &[VectorResult]) -> String {
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    format!(
+        "{} vectors run: {} passed, {} failed",
+        results.len(),
+        results.len() - failed,
+        failed
+    )
+}
+
+pub fn json_report(source: &str, results: &[VectorResult]) -> Value {
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    let vectors: Vec<Value> = results
+        .iter()
+        .map(|r| {
+            json!({
+                "n": r.n,
+                "passed": r.passed(),
+                "failure": r.failure,
+                "duration_ms": r.duration.as_secs_f64() * 1000.0,
+            })
+        })
+        .collect();
+    json!({
+        "source": source,
+        "total": results.len(),
+        "passed": results.len() - failed,
+        "failed": failed,
+        "vectors": vectors,
+    })
+}
+
+pub fn junit_report(source: &str, results: &[VectorResult]) -> String {
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    let total_time: f64 = results.iter().map(|r| r.duration.as_secs_f64()).sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites tests=\"{}\" failures=\"{}\" time=\"{:.6}\">\n",
+        results.len(),
+        failed,
+        total_time
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" time=\"{:.6}\">\n",
+        xml_escape(source),
+        results.len(),
+        failed,
+        total_time
+    ));
+    for r in results.iter() {
+        xml.push_str(&format!(
+            "    <testcase classname=\"metadata_policy\" name=\"vector {}\" time=\"{:.6}\"",
+            r.n,
+            r.duration.as_secs_f64()
+        ));
+        match &r.failure {
+            Some(message) => {
+                xml.push_str(&format!(
+                    ">\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                    xml_escape(message)
+                ));
+            }
+            None => xml.push_str("/>\n"),
+        }
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
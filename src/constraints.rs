@@ -0,0 +1,148 @@
+// Trust chain constraints, OpenID Federation section 6.2. A superior sets them in the
+// constraints claim of its subordinate statement, and they hold for every entity below.
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+
+use crate::statement::EntityStatement;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Constraints {
+    // Largest number of intermediates between the superior and the leaf
+    pub max_path_length: Option<u64>,
+    pub permitted: Option<Vec<String>>,
+    pub excluded: Option<Vec<String>>,
+    // federation_entity is always allowed
+    pub allowed_entity_types: Option<Vec<String>>,
+}
+
+impl Constraints {
+    pub fn from_claim(constraints: &Map<String, Value>) -> Result<Constraints> {
+        let strings = |value: Option<&Value>, name: &str| -> Result<Option<Vec<String>>> {
+            let Some(value) = value else {
+                return Ok(None);
+            };
+            let strings: Option<Vec<String>> = value
+                .as_array()
+                .and_then(|a| a.iter().map(|s| s.as_str().map(String::from)).collect());
+            match strings {
+                Some(strings) => Ok(Some(strings)),
+                None => bail!("Constraints error: {} must be an array of strings", name),
+            }
+        };
+        let max_path_length = match constraints.get("max_path_length") {
+            None => None,
+            Some(value) => match value.as_u64() {
+                Some(n) => Some(n),
+                None => bail!("Constraints error: max_path_length must be a non-negative integer"),
+            },
+        };
+        let naming = match constraints.get("naming_constraints") {
+            None => &Map::new(),
+            Some(Value::Object(naming)) => naming,
+            Some(_) => bail!("Constraints error: naming_constraints must be a JSON object"),
+        };
+        Ok(Constraints {
+            max_path_length,
+            permitted: strings(naming.get("permitted"), "permitted")?,
+            excluded: strings(naming.get("excluded"), "excluded")?,
+            allowed_entity_types: strings(
+                constraints.get("allowed_entity_types"),
+                "allowed_entity_types",
+            )?,
+        })
+    }
+
+    // Whether the naming constraints allow the entity ID.
+    pub fn permits_name(&self, entity_id: &str) -> bool {
+        let host = host(entity_id);
+        let matches = |names: &Option<Vec<String>>| {
+            names.iter().flatten().any(|name| name_matches(name, &host))
+        };
+        if matches(&self.excluded) {
+            return false;
+        }
+        self.permitted.is_none() || matches(&self.permitted)
+    }
+
+    pub fn permits_entity_type(&self, entity_type: &str) -> bool {
+        entity_type == "federation_entity"
+            || self
+                .allowed_entity_types
+                .as_ref()
+                .is_none_or(|allowed| allowed.iter().any(|t| t == entity_type))
+    }
+}
+
+// The host of an entity ID, lower case
+fn host(entity_id: &str) -> String {
+    let rest = entity_id
+        .split_once("://")
+        .map_or(entity_id, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    host.split(':')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+// RFC 5280 section 4.2.1.10: ".example.com" matches every host below example.com,
+// "example.com" only that host
+fn name_matches(name: &str, host: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    if name.starts_with('.') {
+        host.ends_with(&name)
+    } else {
+        host == name
+    }
+}
+
+// Checks the constraints of every subordinate statement of a chain (the leaf first)
+// against the entities below its issuer.
+pub fn check_constraints(statements: &[EntityStatement]) -> Result<()> {
+    for (j, statement) in statements.iter().enumerate().skip(1) {
+        let Some(claim) = &statement.constraints else {
+            continue;
+        };
+        let constraints = Constraints::from_claim(claim)?;
+        // Statement 1 is by the superior of the leaf, with no intermediate in between
+        let path_length = (j - 1) as u64;
+        if constraints
+            .max_path_length
+            .is_some_and(|max| path_length > max)
+        {
+            bail!(
+                "Constraints error: {} allows {} intermediates below it, the chain has {}",
+                statement.iss,
+                constraints.max_path_length.unwrap(),
+                path_length
+            );
+        }
+        for below in statements[..=j].iter() {
+            if !constraints.permits_name(&below.sub) {
+                bail!(
+                    "Constraints error: the naming constraints of {} do not allow {}",
+                    statement.iss,
+                    below.sub
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+// The metadata without the entity types which a constraint in the chain does not allow.
+pub fn strip_entity_types(
+    statements: &[EntityStatement],
+    metadata: &Map<String, Value>,
+) -> Result<Map<String, Value>> {
+    let mut result = metadata.clone();
+    for statement in statements.iter().skip(1) {
+        let Some(claim) = &statement.constraints else {
+            continue;
+        };
+        let constraints = Constraints::from_claim(claim)?;
+        result.retain(|entity_type, _| constraints.permits_entity_type(entity_type));
+    }
+    Ok(result)
+}
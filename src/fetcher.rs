@@ -0,0 +1,276 @@
+// Fetching entity statements from a federation, OpenID Federation sections 9 and 8.1.
+// The chain builder works through FetchingStore on any FederationFetcher: HttpFetcher
+// (the http feature) talks to the federation, MockFetcher serves statements from memory
+// for tests.
+use anyhow::{Result, bail};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::chain::{
+    ChainResolution, ChainSelection, TrustAnchor, ValidatedChain, build_trust_chains,
+};
+use crate::jws::Jws;
+use crate::store::{StatementStore, statement_ids};
+use crate::trust_mark::TrustMarkChecker;
+
+pub trait FederationFetcher {
+    // The signed entity configuration at /.well-known/openid-federation of the entity,
+    // None when there is none.
+    fn entity_configuration(&self, entity_id: &str) -> Result<Option<String>>;
+
+    // The subordinate statement about subject from a fetch endpoint, None when the
+    // issuer has none.
+    fn fetch(&self, fetch_endpoint: &str, subject: &str) -> Result<Option<String>>;
+}
+
+pub fn well_known_url(entity_id: &str) -> Result<String> {
+    check_https(entity_id)?;
+    Ok(format!(
+        "{}/.well-known/openid-federation",
+        entity_id.trim_end_matches('/')
+    ))
+}
+
+pub fn fetch_url(fetch_endpoint: &str, subject: &str) -> Result<String> {
+    check_https(fetch_endpoint)?;
+    let separator = if fetch_endpoint.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    Ok(format!(
+        "{}{}sub={}",
+        fetch_endpoint,
+        separator,
+        url_encode(subject)
+    ))
+}
+
+// Entity IDs and the endpoints of a federation are https URLs, OpenID Federation
+// section 1.2, nothing is fetched over plain HTTP
+fn check_https(url: &str) -> Result<()> {
+    if !url.starts_with("https://") {
+        bail!("Fetch error: {} is not an https URL", url);
+    }
+    Ok(())
+}
+
+fn url_encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// The federation_fetch_endpoint of an entity configuration, without verifying it
+fn fetch_endpoint(configuration: &str) -> Result<String> {
+    let jws = Jws::parse(configuration)?;
+    match jws.payload["metadata"]["federation_entity"]["federation_fetch_endpoint"].as_str() {
+        Some(endpoint) => Ok(endpoint.to_string()),
+        None => bail!("Fetch error: the entity configuration has no federation_fetch_endpoint"),
+    }
+}
+
+// A StatementStore which fetches the statements, the entity configurations are kept
+pub struct FetchingStore<'a> {
+    fetcher: &'a dyn FederationFetcher,
+    configurations: RefCell<HashMap<String, Option<String>>>,
+}
+
+impl<'a> FetchingStore<'a> {
+    pub fn new(fetcher: &'a dyn FederationFetcher) -> FetchingStore<'a> {
+        FetchingStore {
+            fetcher,
+            configurations: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl StatementStore for FetchingStore<'_> {
+    fn statement(&self, issuer: &str, subject: &str) -> Result<Option<String>> {
+        let configuration = self.configurations.borrow().get(issuer).cloned();
+        let configuration = match configuration {
+            Some(configuration) => configuration,
+            None => {
+                let fetched = self.fetcher.entity_configuration(issuer)?;
+                self.configurations
+                    .borrow_mut()
+                    .insert(issuer.to_string(), fetched.clone());
+                fetched
+            }
+        };
+        let Some(configuration) = configuration else {
+            return Ok(None);
+        };
+        if issuer == subject {
+            return Ok(Some(configuration));
+        }
+        self.fetcher
+            .fetch(&fetch_endpoint(&configuration)?, subject)
+    }
+}
+
+// Discovers the trust chains of a leaf through the fetcher and validates them, with the
+// trust marks of the leaf, at the time now. Returns the valid chains, and the problems
+// with the other paths.
+pub fn resolve_entity(
+    leaf: &str,
+    trust_anchors: &[TrustAnchor],
+    fetcher: &dyn FederationFetcher,
+    now: i64,
+) -> Result<(Vec<ValidatedChain>, Vec<String>)> {
+    let store = FetchingStore::new(fetcher);
+    let search = build_trust_chains(leaf, trust_anchors, &store)?;
+    let (mut valid, problems) = search.validate(trust_anchors, now);
+    // The issuers are shared by the chains, their keys are only looked up once
+    let checker = TrustMarkChecker::new(&store, now);
+    for chain in valid.iter_mut() {
+        chain.check_trust_marks_with(&checker);
+    }
+    Ok((valid, problems))
+}
+
+// Discovers and validates the trust chains of a leaf through the fetcher, and picks one
+// of the valid chains. The trust marks are checked for the chosen chain.
+pub fn select_entity_chain(
+    leaf: &str,
+    trust_anchors: &[TrustAnchor],
+    fetcher: &dyn FederationFetcher,
+    now: i64,
+    selection: &ChainSelection,
+) -> Result<ChainResolution> {
+    let store = FetchingStore::new(fetcher);
+    let search = build_trust_chains(leaf, trust_anchors, &store)?;
+    let mut resolution = search.resolve(trust_anchors, now, selection);
+    if let Some(n) = resolution.selected
+        && let Ok(chain) = &mut resolution.outcomes[n].result
+    {
+        chain.check_trust_marks(&store, now);
+    }
+    Ok(resolution)
+}
+
+// A federation in memory, answering from the statements added to it
+#[derive(Debug, Default)]
+pub struct MockFetcher {
+    // URL to the signed statement
+    responses: HashMap<String, String>,
+    requests: RefCell<Vec<String>>,
+}
+
+impl MockFetcher {
+    pub fn new() -> MockFetcher {
+        MockFetcher::default()
+    }
+
+    // Serves an entity configuration at the well-known URL of its iss.
+    pub fn add_entity_configuration(&mut self, token: &str) -> Result<()> {
+        let (iss, sub) = statement_ids(token)?;
+        if iss != sub {
+            bail!("Fetch error: {} is not an entity configuration", token);
+        }
+        self.responses
+            .insert(well_known_url(&iss)?, token.trim().to_string());
+        Ok(())
+    }
+
+    // Serves a subordinate statement at the fetch endpoint of its issuer.
+    pub fn add_subordinate_statement(&mut self, fetch_endpoint: &str, token: &str) -> Result<()> {
+        let (_, sub) = statement_ids(token)?;
+        self.responses
+            .insert(fetch_url(fetch_endpoint, &sub)?, token.trim().to_string());
+        Ok(())
+    }
+
+    // The URLs asked for, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.borrow().clone()
+    }
+
+    fn get(&self, url: String) -> Result<Option<String>> {
+        let response = self.responses.get(&url).cloned();
+        self.requests.borrow_mut().push(url);
+        Ok(response)
+    }
+}
+
+impl FederationFetcher for MockFetcher {
+    fn entity_configuration(&self, entity_id: &str) -> Result<Option<String>> {
+        self.get(well_known_url(entity_id)?)
+    }
+
+    fn fetch(&self, fetch_endpoint: &str, subject: &str) -> Result<Option<String>> {
+        self.get(fetch_url(fetch_endpoint, subject)?)
+    }
+}
+
+// Fetches over HTTP, a 404 is a missing statement
+#[cfg(feature = "http")]
+pub struct HttpFetcher {
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "http")]
+impl HttpFetcher {
+    pub fn new() -> HttpFetcher {
+        // check_https only sees the URLs we build, https_only also refuses redirects
+        // to plain http
+        let config = ureq::Agent::config_builder()
+            .timeout_global(Some(std::time::Duration::from_secs(10)))
+            .https_only(true)
+            .build();
+        HttpFetcher {
+            agent: config.into(),
+        }
+    }
+
+    // The agent the statements are fetched with
+    pub fn agent(&self) -> &ureq::Agent {
+        &self.agent
+    }
+
+    fn get(&self, url: &str) -> Result<Option<String>> {
+        use anyhow::Context;
+
+        let response = match self
+            .agent
+            .get(url)
+            .header("Accept", "application/entity-statement+jwt")
+            .call()
+        {
+            Ok(response) => response,
+            Err(ureq::Error::StatusCode(404)) => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Fetch error: GET {}", url)),
+        };
+        let body = response
+            .into_body()
+            .read_to_string()
+            .with_context(|| format!("Fetch error: GET {}", url))?;
+        Ok(Some(body))
+    }
+}
+
+#[cfg(feature = "http")]
+impl Default for HttpFetcher {
+    fn default() -> HttpFetcher {
+        HttpFetcher::new()
+    }
+}
+
+#[cfg(feature = "http")]
+impl FederationFetcher for HttpFetcher {
+    fn entity_configuration(&self, entity_id: &str) -> Result<Option<String>> {
+        self.get(&well_known_url(entity_id)?)
+    }
+
+    fn fetch(&self, fetch_endpoint: &str, subject: &str) -> Result<Option<String>> {
+        self.get(&fetch_url(fetch_endpoint, subject)?)
+    }
+}
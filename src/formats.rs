@@ -0,0 +1,113 @@
+// Reads policy and metadata documents written in JSON, YAML or TOML into the
+// serde_json::Value structures used everywhere else.
+//
+// The parse errors of all three formats include the line and column. TOML has no null,
+// so a `value` operator removing a parameter can only be written in JSON or YAML. TOML
+// dates and times become strings.
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    #[cfg(feature = "yaml")]
+    Yaml,
+    #[cfg(feature = "toml")]
+    Toml,
+}
+
+impl Format {
+    // Picks the format from the file extension, JSON when it is not known. YAML and TOML
+    // files are an error when their feature is not enabled.
+    pub fn from_path(path: &Path) -> Result<Format> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            #[cfg(feature = "yaml")]
+            Some("yaml") | Some("yml") => Ok(Format::Yaml),
+            #[cfg(not(feature = "yaml"))]
+            Some("yaml") | Some("yml") => Err(not_enabled(path, "YAML", "yaml")),
+            #[cfg(feature = "toml")]
+            Some("toml") => Ok(Format::Toml),
+            #[cfg(not(feature = "toml"))]
+            Some("toml") => Err(not_enabled(path, "TOML", "toml")),
+            _ => Ok(Format::Json),
+        }
+    }
+}
+
+#[cfg(not(all(feature = "yaml", feature = "toml")))]
+fn not_enabled(path: &Path, format: &str, feature: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "{} is a {} file, but the {} feature is not enabled",
+        path.display(),
+        format,
+        feature
+    )
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Json => write!(f, "JSON"),
+            #[cfg(feature = "yaml")]
+            Format::Yaml => write!(f, "YAML"),
+            #[cfg(feature = "toml")]
+            Format::Toml => write!(f, "TOML"),
+        }
+    }
+}
+
+pub fn parse_document(text: &str, format: Format) -> Result<Value> {
+    match format {
+        Format::Json => Ok(serde_json::from_str(text)?),
+        #[cfg(feature = "yaml")]
+        Format::Yaml => Ok(serde_norway::from_str(text)?),
+        #[cfg(feature = "toml")]
+        Format::Toml => {
+            let table: toml::Table = toml::from_str(text)?;
+            toml_to_json(toml::Value::Table(table))
+        }
+    }
+}
+
+pub fn read_document(path: &Path) -> Result<Value> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let format = Format::from_path(path)?;
+    parse_document(&text, format)
+        .with_context(|| format!("Failed to parse {} as {}", path.display(), format))
+}
+
+#[cfg(feature = "toml")]
+fn toml_to_json(value: toml::Value) -> Result<Value> {
+    Ok(match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => match serde_json::Number::from_f64(f) {
+            Some(n) => Value::Number(n),
+            None => anyhow::bail!("{} can not be represented in JSON", f),
+        },
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(toml_to_json)
+                .collect::<Result<Vec<Value>>>()?,
+        ),
+        toml::Value::Table(table) => {
+            let mut map = serde_json::Map::new();
+            for (key, value) in table.into_iter() {
+                let value = toml_to_json(value).with_context(|| format!("In {}", key))?;
+                map.insert(key, value);
+            }
+            Value::Object(map)
+        }
+    })
+}
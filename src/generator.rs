@@ -0,0 +1,189 @@
+// Generates metadata policy test vectors in the connect2id format
+// (`n`, `TA`, `INT`, `merged`, `metadata`, `resolved`, `error`).
+//
+// Every combination of up to `max_operators` operators is tried for the TA and for the
+// intermediate, and each merged policy is applied to a few metadata values. The expected
+// outcomes come from merge_policies and resolve_metadata_policy, so the vectors record
+// the behaviour of this implementation and should be reviewed before they are shared.
+use serde_json::{Map, Value, json};
+
+use crate::{merge_policies, resolve_metadata_policy};
+
+// One parameter to explore, with the operator values and metadata values to combine.
+#[derive(Debug, Clone)]
+pub struct ParameterSpec {
+    pub name: String,
+    // Candidate values for each operator, an operator is used with one of them at a time
+    pub operators: Vec<(String, Vec<Value>)>,
+    // Metadata values to resolve against, None means the parameter is not in the metadata
+    pub metadata: Vec<Option<Value>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    pub parameters: Vec<ParameterSpec>,
+    // Largest number of operators in the TA and in the intermediate policy
+    pub max_operators: usize,
+    // Number of the first generated vector
+    pub first_number: i64,
+    // Stop after this many vectors
+    pub limit: Option<usize>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            parameters: default_parameters(),
+            max_operators: 2,
+            first_number: 1,
+            limit: None,
+        }
+    }
+}
+
+// A list valued and a single valued parameter covering all the standard operators.
+pub fn default_parameters() -> Vec<ParameterSpec> {
+    vec![
+        ParameterSpec {
+            name: "grant_types".to_string(),
+            operators: vec![
+                (
+                    "value".to_string(),
+                    vec![json!(["authorization_code"]), Value::Null],
+                ),
+                ("add".to_string(), vec![json!(["refresh_token"])]),
+                ("default".to_string(), vec![json!(["authorization_code"])]),
+                (
+                    "subset_of".to_string(),
+                    vec![
+                        json!(["authorization_code", "refresh_token"]),
+                        json!(["implicit"]),
+                    ],
+                ),
+                (
+                    "superset_of".to_string(),
+                    vec![json!(["authorization_code"])],
+                ),
+                ("essential".to_string(), vec![json!(true), json!(false)]),
+            ],
+            metadata: vec![
+                None,
+                Some(json!(["authorization_code"])),
+                Some(json!(["implicit", "refresh_token"])),
+            ],
+        },
+        ParameterSpec {
+            name: "token_endpoint_auth_method".to_string(),
+            operators: vec![
+                (
+                    "value".to_string(),
+                    vec![json!("private_key_jwt"), Value::Null],
+                ),
+                ("default".to_string(), vec![json!("private_key_jwt")]),
+                (
+                    "one_of".to_string(),
+                    vec![
+                        json!(["private_key_jwt", "self_signed_tls_client_auth"]),
+                        json!(["private_key_jwt"]),
+                    ],
+                ),
+                ("essential".to_string(), vec![json!(true), json!(false)]),
+            ],
+            metadata: vec![
+                None,
+                Some(json!("private_key_jwt")),
+                Some(json!("client_secret_basic")),
+            ],
+        },
+    ]
+}
+
+// Every operator map for one parameter with at most max_operators operators.
+pub fn operator_combinations(
+    operators: &[(String, Vec<Value>)],
+    max_operators: usize,
+) -> Vec<Map<String, Value>> {
+    let mut result = vec![Map::new()];
+    for (name, candidates) in operators.iter() {
+        let mut next = Vec::new();
+        for existing in result.iter() {
+            // Without this operator
+            next.push(existing.clone());
+            if existing.len() >= max_operators {
+                continue;
+            }
+            for candidate in candidates.iter() {
+                let mut with = existing.clone();
+                with.insert(name.clone(), candidate.clone());
+                next.push(with);
+            }
+        }
+        result = next;
+    }
+    result
+}
+
+pub fn generate_test_vectors(config: &GeneratorConfig) -> Vec<Value> {
+    let mut vectors = Vec::new();
+    let mut n = config.first_number;
+    for parameter in config.parameters.iter() {
+        let policies = operator_combinations(&parameter.operators, config.max_operators);
+        for ta_ops in policies.iter() {
+            for int_ops in policies.iter() {
+                // Nothing to test when neither side has a policy
+                if ta_ops.is_empty() && int_ops.is_empty() {
+                    continue;
+                }
+                let ta = policy_for(&parameter.name, ta_ops);
+                let int = policy_for(&parameter.name, int_ops);
+                let merged = match merge_policies(&ta, &int) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        vectors.push(json!({
+                            "n": n,
+                            "TA": ta,
+                            "INT": int,
+                            "error": e.to_string(),
+                        }));
+                        n += 1;
+                        if config.limit.is_some_and(|l| vectors.len() >= l) {
+                            return vectors;
+                        }
+                        continue;
+                    }
+                };
+                for metadata_value in parameter.metadata.iter() {
+                    let mut metadata = Map::new();
+                    if let Some(v) = metadata_value {
+                        metadata.insert(parameter.name.clone(), v.clone());
+                    }
+                    let mut vector = json!({
+                        "n": n,
+                        "TA": ta,
+                        "INT": int,
+                        "merged": merged,
+                        "metadata": metadata,
+                    });
+                    match resolve_metadata_policy(&merged, &metadata) {
+                        Ok(resolved) => vector["resolved"] = resolved,
+                        Err(e) => vector["error"] = json!(e.to_string()),
+                    }
+                    vectors.push(vector);
+                    n += 1;
+                    if config.limit.is_some_and(|l| vectors.len() >= l) {
+                        return vectors;
+                    }
+                }
+            }
+        }
+    }
+    vectors
+}
+
+fn policy_for(name: &str, operators: &Map<String, Value>) -> Value {
+    if operators.is_empty() {
+        json!({})
+    } else {
+        json!({ name: operators })
+    }
+}
@@ -0,0 +1,186 @@
+// Who breaks when a superior changes its metadata_policy: resolves the metadata of every
+// subordinate under the old and the new policy and compares the outcomes.
+use serde_json::{Map, Value, json};
+
+use crate::resolve_metadata_policy;
+use crate::trace::diff_metadata;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    // Resolves to the same metadata under both policies
+    Unaffected,
+    // Resolves under both policies, but to different metadata
+    Changed,
+    // Resolved under the old policy, fails under the new one
+    Breaks,
+    // Failed under the old policy, resolves under the new one
+    Fixed,
+    StillFails,
+}
+
+impl Transition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Transition::Unaffected => "unaffected",
+            Transition::Changed => "changed",
+            Transition::Breaks => "breaks",
+            Transition::Fixed => "fixed",
+            Transition::StillFails => "still_fails",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangedParameter {
+    // None for the metadata of a single entity type
+    pub entity_type: Option<String>,
+    pub parameter: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubordinateImpact {
+    pub subordinate: String,
+    pub transition: Transition,
+    pub changed_parameters: Vec<ChangedParameter>,
+    // Errors from the new policy which the old policy did not give
+    pub new_errors: Vec<String>,
+}
+
+impl SubordinateImpact {
+    pub fn to_json(&self) -> Value {
+        let changed: Vec<Value> = self
+            .changed_parameters
+            .iter()
+            .map(|c| {
+                json!({
+                    "entity_type": c.entity_type,
+                    "parameter": c.parameter,
+                    "old": c.old,
+                    "new": c.new,
+                })
+            })
+            .collect();
+        json!({
+            "subordinate": self.subordinate,
+            "transition": self.transition.as_str(),
+            "changed_parameters": changed,
+            "new_errors": self.new_errors,
+        })
+    }
+}
+
+// Impact of changing the policy for one entity type, the subordinates are given as
+// (name, metadata of that entity type).
+pub fn policy_change_impact(
+    old_policy: &Map<String, Value>,
+    new_policy: &Map<String, Value>,
+    subordinates: &[(String, Map<String, Value>)],
+) -> Vec<SubordinateImpact> {
+    subordinates
+        .iter()
+        .map(|(name, metadata)| {
+            let mut outcome = Outcome::default();
+            outcome.add(None, Some(old_policy), Some(new_policy), metadata);
+            outcome.finish(name)
+        })
+        .collect()
+}
+
+// Impact of changing a metadata_policy claim, the subordinates are given as
+// (name, metadata claim with a member for each entity type).
+pub fn metadata_policy_change_impact(
+    old_policy: &Map<String, Value>,
+    new_policy: &Map<String, Value>,
+    subordinates: &[(String, Map<String, Value>)],
+) -> Vec<SubordinateImpact> {
+    subordinates
+        .iter()
+        .map(|(name, metadata)| {
+            let mut outcome = Outcome::default();
+            for (entity_type, entity_metadata) in metadata.iter() {
+                let Some(entity_metadata) = entity_metadata.as_object() else {
+                    let error = format!("{}: metadata must be a JSON object", entity_type);
+                    outcome.old_errors.push(error.clone());
+                    outcome.new_errors.push(error);
+                    continue;
+                };
+                outcome.add(
+                    Some(entity_type),
+                    old_policy.get(entity_type).and_then(Value::as_object),
+                    new_policy.get(entity_type).and_then(Value::as_object),
+                    entity_metadata,
+                );
+            }
+            outcome.finish(name)
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Outcome {
+    changed: Vec<ChangedParameter>,
+    old_errors: Vec<String>,
+    new_errors: Vec<String>,
+}
+
+impl Outcome {
+    fn add(
+        &mut self,
+        entity_type: Option<&String>,
+        old_policy: Option<&Map<String, Value>>,
+        new_policy: Option<&Map<String, Value>>,
+        metadata: &Map<String, Value>,
+    ) {
+        let resolve = |policy: Option<&Map<String, Value>>| match policy {
+            Some(p) => resolve_metadata_policy(p, metadata).map_err(|e| match entity_type {
+                Some(t) => format!("{}: {}", t, e),
+                None => e.to_string(),
+            }),
+            None => Ok(Value::Object(metadata.clone())),
+        };
+        match (resolve(old_policy), resolve(new_policy)) {
+            (Ok(old), Ok(new)) => {
+                let changes = diff_metadata(old.as_object().unwrap(), new.as_object().unwrap());
+                for change in changes.into_iter() {
+                    self.changed.push(ChangedParameter {
+                        entity_type: entity_type.cloned(),
+                        parameter: change.parameter,
+                        old: change.before,
+                        new: change.after,
+                    });
+                }
+            }
+            (old, new) => {
+                if let Err(e) = old {
+                    self.old_errors.push(e);
+                }
+                if let Err(e) = new {
+                    self.new_errors.push(e);
+                }
+            }
+        }
+    }
+
+    fn finish(self, subordinate: &str) -> SubordinateImpact {
+        let transition = match (self.old_errors.is_empty(), self.new_errors.is_empty()) {
+            (true, true) if self.changed.is_empty() => Transition::Unaffected,
+            (true, true) => Transition::Changed,
+            (true, false) => Transition::Breaks,
+            (false, true) => Transition::Fixed,
+            (false, false) => Transition::StillFails,
+        };
+        let new_errors = self
+            .new_errors
+            .into_iter()
+            .filter(|e| !self.old_errors.contains(e))
+            .collect();
+        SubordinateImpact {
+            subordinate: subordinate.to_string(),
+            transition,
+            changed_parameters: self.changed,
+            new_errors,
+        }
+    }
+}
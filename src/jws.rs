@@ -0,0 +1,361 @@
+// Signed entity statements: compact JWS parsing and signature verification with a key
+// from a JWK Set, offline, and signing with a private JWK. Supports RS256, PS256, ES256
+// and EdDSA (Ed25519).
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rsa::signature::{RandomizedSigner, SignatureEncoding, Signer, Verifier};
+use rsa::traits::PublicKeyParts;
+use serde_json::{Map, Value, json};
+use sha2::Sha256;
+
+use crate::statement::{EntityConfiguration, EntityStatement};
+
+// The typ of entity statements, OpenID Federation section 3.1
+pub const ENTITY_STATEMENT_TYPE: &str = "entity-statement+jwt";
+
+// RFC 7518 section 3.3: RSA keys of at least 2048 bits
+pub const MIN_RSA_KEY_BITS: usize = 2048;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jws {
+    pub header: Map<String, Value>,
+    pub payload: Value,
+    // The header and payload as they were signed
+    signing_input: String,
+    signature: Vec<u8>,
+}
+
+impl Jws {
+    // Parses a compact JWS, without verifying the signature.
+    pub fn parse(token: &str) -> Result<Jws> {
+        let parts: Vec<&str> = token.trim().split('.').collect();
+        let [header, payload, signature] = parts[..] else {
+            bail!(
+                "JWS error: a compact JWS has three parts, not {}",
+                parts.len()
+            );
+        };
+        let header: Value = serde_json::from_slice(&decode(header, "header")?)
+            .context("JWS error: the header is not JSON")?;
+        let Value::Object(header) = header else {
+            bail!("JWS error: the header must be a JSON object");
+        };
+        let payload = serde_json::from_slice(&decode(payload, "payload")?)
+            .context("JWS error: the payload is not JSON")?;
+        Ok(Jws {
+            header,
+            payload,
+            signing_input: token.trim()[..token.trim().rfind('.').unwrap()].to_string(),
+            signature: decode(signature, "signature")?,
+        })
+    }
+
+    pub fn alg(&self) -> Option<&str> {
+        self.header.get("alg").and_then(Value::as_str)
+    }
+
+    pub fn kid(&self) -> Option<&str> {
+        self.header.get("kid").and_then(Value::as_str)
+    }
+
+    pub fn typ(&self) -> Option<&str> {
+        self.header.get("typ").and_then(Value::as_str)
+    }
+
+    // Fails unless typ is this media type, with or without the application/ prefix.
+    pub fn check_typ(&self, expected: &str) -> Result<()> {
+        let typ = self.typ().unwrap_or_default();
+        if !typ.eq_ignore_ascii_case(expected)
+            && !typ.eq_ignore_ascii_case(&format!("application/{}", expected))
+        {
+            bail!("JWS error: typ must be {}, not {:?}", expected, typ);
+        }
+        Ok(())
+    }
+
+    // Verifies the signature with the key of the JWK Set which has the kid of the header.
+    pub fn verify(&self, jwks: &Value) -> Result<()> {
+        let Some(kid) = self.kid() else {
+            bail!("JWS error: the header has no kid");
+        };
+        let Some(alg) = self.alg() else {
+            bail!("JWS error: the header has no alg");
+        };
+        let jwk = find_key(jwks, kid)?;
+        if jwk.get("alg").is_some_and(|a| a != alg) {
+            bail!("JWS error: key {} is not for {}", kid, alg);
+        }
+        if jwk.get("use").is_some_and(|u| u != "sig") {
+            bail!("JWS error: key {} is not a signing key", kid);
+        }
+        verify_signature(alg, jwk, self.signing_input.as_bytes(), &self.signature)
+            .with_context(|| format!("JWS error: the signature does not verify with key {}", kid))
+    }
+}
+
+// Verifies a signed entity statement with the keys of the issuer, for a subordinate
+// statement these are in the entity configuration of the issuer.
+pub fn verify_entity_statement(token: &str, jwks: &Value) -> Result<EntityStatement> {
+    let jws = Jws::parse(token)?;
+    jws.check_typ(ENTITY_STATEMENT_TYPE)?;
+    jws.verify(jwks)?;
+    EntityStatement::from_claims(&jws.payload)
+}
+
+// Verifies a signed entity configuration with the keys in its own jwks claim.
+pub fn verify_entity_configuration(token: &str) -> Result<EntityConfiguration> {
+    let jws = Jws::parse(token)?;
+    jws.check_typ(ENTITY_STATEMENT_TYPE)?;
+    let configuration = EntityConfiguration::from_claims(&jws.payload)?;
+    jws.verify(&configuration.jwks)?;
+    Ok(configuration)
+}
+
+enum PrivateKey {
+    Rsa(rsa::RsaPrivateKey),
+    Ec(p256::ecdsa::SigningKey),
+    Ed(ed25519_dalek::SigningKey),
+}
+
+// Signs compact JWS with a private key read from a JWK, the kid of the JWK goes in the
+// header.
+pub struct JwsSigner {
+    kid: String,
+    alg: String,
+    key: PrivateKey,
+}
+
+impl JwsSigner {
+    pub fn from_jwk(jwk: &Value, alg: &str) -> Result<JwsSigner> {
+        let Some(jwk) = jwk.as_object() else {
+            bail!("JWS error: the JWK must be a JSON object");
+        };
+        let Some(kid) = jwk.get("kid").and_then(Value::as_str) else {
+            bail!("JWS error: the key has no kid");
+        };
+        if jwk.get("alg").is_some_and(|a| a != alg) {
+            bail!("JWS error: key {} is not for {}", kid, alg);
+        }
+        let key = private_key(alg, jwk)
+            .with_context(|| format!("JWS error: key {} can not sign {}", kid, alg))?;
+        Ok(JwsSigner {
+            kid: kid.to_string(),
+            alg: alg.to_string(),
+            key,
+        })
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn alg(&self) -> &str {
+        &self.alg
+    }
+
+    // The public key, to publish in a JWK Set
+    pub fn public_jwk(&self) -> Value {
+        match &self.key {
+            PrivateKey::Rsa(key) => json!({
+                "kty": "RSA",
+                "kid": self.kid,
+                "n": URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+                "e": URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+            }),
+            PrivateKey::Ec(key) => {
+                let point = key.verifying_key().to_encoded_point(false);
+                json!({
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "kid": self.kid,
+                    "x": URL_SAFE_NO_PAD.encode(point.x().unwrap()),
+                    "y": URL_SAFE_NO_PAD.encode(point.y().unwrap()),
+                })
+            }
+            PrivateKey::Ed(key) => json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "kid": self.kid,
+                "x": URL_SAFE_NO_PAD.encode(key.verifying_key().as_bytes()),
+            }),
+        }
+    }
+
+    // A compact JWS of the claims with this typ.
+    pub fn sign(&self, typ: &str, claims: &Value) -> Result<String> {
+        let header = json!({"alg": self.alg, "kid": self.kid, "typ": typ});
+        let input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?)
+        );
+        let message = input.as_bytes();
+        let signature = match &self.key {
+            // With an RNG the private key operation is blinded, against timing attacks
+            PrivateKey::Rsa(key) if self.alg == "RS256" => {
+                rsa::pkcs1v15::SigningKey::<Sha256>::new(key.clone())
+                    .try_sign_with_rng(&mut rsa::rand_core::OsRng, message)?
+                    .to_vec()
+            }
+            PrivateKey::Rsa(key) => rsa::pss::BlindedSigningKey::<Sha256>::new(key.clone())
+                .try_sign_with_rng(&mut rsa::rand_core::OsRng, message)?
+                .to_vec(),
+            PrivateKey::Ec(key) => {
+                let signature: p256::ecdsa::Signature = key.try_sign(message)?;
+                signature.to_vec()
+            }
+            PrivateKey::Ed(key) => key.try_sign(message)?.to_vec(),
+        };
+        Ok(format!("{}.{}", input, URL_SAFE_NO_PAD.encode(signature)))
+    }
+}
+
+// Without the private key
+impl std::fmt::Debug for JwsSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwsSigner")
+            .field("kid", &self.kid)
+            .field("alg", &self.alg)
+            .finish_non_exhaustive()
+    }
+}
+
+fn private_key(alg: &str, jwk: &Map<String, Value>) -> Result<PrivateKey> {
+    let kty = jwk.get("kty").and_then(Value::as_str);
+    let crv = jwk.get("crv").and_then(Value::as_str);
+    match alg {
+        "RS256" | "PS256" => {
+            if kty != Some("RSA") {
+                bail!("the key is not an RSA key");
+            }
+            let number = |name: &str| {
+                Ok::<_, anyhow::Error>(rsa::BigUint::from_bytes_be(&member(jwk, name)?))
+            };
+            let n = number("n")?;
+            check_rsa_size(&n)?;
+            let key = rsa::RsaPrivateKey::from_components(
+                n,
+                number("e")?,
+                number("d")?,
+                vec![number("p")?, number("q")?],
+            )?;
+            Ok(PrivateKey::Rsa(key))
+        }
+        "ES256" => {
+            if kty != Some("EC") || crv != Some("P-256") {
+                bail!("the key is not a P-256 key");
+            }
+            let key = p256::ecdsa::SigningKey::from_slice(&coordinate(jwk, "d")?)?;
+            Ok(PrivateKey::Ec(key))
+        }
+        "EdDSA" => {
+            if kty != Some("OKP") || crv != Some("Ed25519") {
+                bail!("the key is not an Ed25519 key");
+            }
+            let key = ed25519_dalek::SigningKey::from_bytes(&coordinate(jwk, "d")?);
+            Ok(PrivateKey::Ed(key))
+        }
+        _ => bail!("{} is not supported", alg),
+    }
+}
+
+fn decode(part: &str, name: &str) -> Result<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .with_context(|| format!("JWS error: the {} is not base64url", name))
+}
+
+fn find_key<'a>(jwks: &'a Value, kid: &str) -> Result<&'a Map<String, Value>> {
+    let Some(keys) = jwks.get("keys").and_then(Value::as_array) else {
+        bail!("JWS error: the JWK Set has no keys");
+    };
+    let mut found = keys
+        .iter()
+        .filter_map(Value::as_object)
+        .filter(|k| k.get("kid").and_then(Value::as_str) == Some(kid));
+    match (found.next(), found.next()) {
+        (Some(key), None) => Ok(key),
+        (None, _) => bail!("JWS error: no key with kid {}", kid),
+        (Some(_), Some(_)) => bail!("JWS error: more than one key with kid {}", kid),
+    }
+}
+
+fn member(jwk: &Map<String, Value>, name: &str) -> Result<Vec<u8>> {
+    let Some(value) = jwk.get(name).and_then(Value::as_str) else {
+        bail!("the key has no {}", name);
+    };
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .with_context(|| format!("{} of the key is not base64url", name))
+}
+
+// A 32 byte member, the coordinates and private keys of P-256 and Ed25519 keys
+fn coordinate(jwk: &Map<String, Value>, name: &str) -> Result<[u8; 32]> {
+    match <[u8; 32]>::try_from(member(jwk, name)?) {
+        Ok(bytes) => Ok(bytes),
+        Err(_) => bail!("{} of the key must be 32 bytes", name),
+    }
+}
+
+fn rsa_key(jwk: &Map<String, Value>) -> Result<rsa::RsaPublicKey> {
+    if jwk.get("kty").and_then(Value::as_str) != Some("RSA") {
+        bail!("the key is not an RSA key");
+    }
+    let n = rsa::BigUint::from_bytes_be(&member(jwk, "n")?);
+    let e = rsa::BigUint::from_bytes_be(&member(jwk, "e")?);
+    check_rsa_size(&n)?;
+    Ok(rsa::RsaPublicKey::new(n, e)?)
+}
+
+fn check_rsa_size(n: &rsa::BigUint) -> Result<()> {
+    if n.bits() < MIN_RSA_KEY_BITS {
+        bail!(
+            "the RSA key has {} bits, at least {} are needed",
+            n.bits(),
+            MIN_RSA_KEY_BITS
+        );
+    }
+    Ok(())
+}
+
+fn verify_signature(
+    alg: &str,
+    jwk: &Map<String, Value>,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    let kty = jwk.get("kty").and_then(Value::as_str);
+    let crv = jwk.get("crv").and_then(Value::as_str);
+    match alg {
+        "RS256" => {
+            let key = rsa::pkcs1v15::VerifyingKey::<Sha256>::new(rsa_key(jwk)?);
+            key.verify(message, &rsa::pkcs1v15::Signature::try_from(signature)?)?;
+        }
+        "PS256" => {
+            let key = rsa::pss::VerifyingKey::<Sha256>::new(rsa_key(jwk)?);
+            key.verify(message, &rsa::pss::Signature::try_from(signature)?)?;
+        }
+        "ES256" => {
+            if kty != Some("EC") || crv != Some("P-256") {
+                bail!("the key is not a P-256 key");
+            }
+            let point = p256::EncodedPoint::from_affine_coordinates(
+                &coordinate(jwk, "x")?.into(),
+                &coordinate(jwk, "y")?.into(),
+                false,
+            );
+            let key = p256::ecdsa::VerifyingKey::from_encoded_point(&point)?;
+            key.verify(message, &p256::ecdsa::Signature::from_slice(signature)?)?;
+        }
+        "EdDSA" => {
+            if kty != Some("OKP") || crv != Some("Ed25519") {
+                bail!("the key is not an Ed25519 key");
+            }
+            let key = ed25519_dalek::VerifyingKey::from_bytes(&coordinate(jwk, "x")?)?;
+            key.verify_strict(message, &ed25519_dalek::Signature::from_slice(signature)?)?;
+        }
+        _ => bail!("{} is not supported", alg),
+    }
+    Ok(())
+}
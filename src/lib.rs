@@ -1,16 +1,51 @@
-use anyhow::{Result, bail};
+pub mod batch;
+pub mod builder;
+#[cfg(feature = "jose")]
+pub mod chain;
+pub mod conformance;
+pub mod constraints;
+#[cfg(feature = "jose")]
+pub mod fetcher;
+pub mod formats;
+pub mod generator;
+pub mod impact;
+#[cfg(feature = "jose")]
+pub mod jws;
+pub mod lint;
+pub mod operators;
+pub mod policy_diff;
+pub mod policy_file;
+pub mod remediation;
+#[cfg(feature = "jose")]
+pub mod resolve;
+pub mod schema;
+pub mod statement;
+#[cfg(feature = "jose")]
+pub mod store;
+pub mod trace;
+#[cfg(feature = "jose")]
+pub mod trust_mark;
+
+use anyhow::{Context, Result, bail};
 use log::debug;
 use serde_json::{Map, Value, json};
 
 use std::collections::HashSet;
 
+use crate::operators::{broken_combination, check_policy};
+
 pub fn merge_policies(
     ta_policies_in: &Value,
     ia_policies_in: &Value,
 ) -> Result<Map<String, Value>> {
     // Both the input has to be maps
-    let ta_policies = ta_policies_in.as_object().unwrap();
-    let ia_policies = ia_policies_in.as_object().unwrap();
+    let (Some(ta_policies), Some(ia_policies)) =
+        (ta_policies_in.as_object(), ia_policies_in.as_object())
+    else {
+        bail!("Policy error: the policy must be a JSON object");
+    };
+    check_policy(ta_policies)?;
+    check_policy(ia_policies)?;
 
     debug!("From TA: {:?}\n", ta_policies);
     debug!("From IA: {:?}\n", ia_policies);
@@ -21,6 +56,9 @@ pub fn merge_policies(
         //debug!("metadata value {:?}\n", value);
         // First scenario when we have in TA but not in IA
         if !ia_policies.contains_key(oid_meta_name) {
+            if let Some(rule) = broken_combination(value.as_object().unwrap()) {
+                bail!("Subordinate policy merge error: {}", rule.message);
+            }
             // directly copy over to merged
             merged.insert(oid_meta_name.clone(), value.clone());
             continue;
@@ -32,31 +70,27 @@ pub fn merge_policies(
         // oid_meata_name == "grant_type"
         // This will hold the details for oid_meta_names
         //let mut lres = Map::new();
-        // First the ones in ta but not in ia
-        let mut ta_names: HashSet<String> = HashSet::new();
-        for name in list_of_policies.keys() {
-            ta_names.insert(name.clone());
-        }
-        // We have all the names from ta
-        let mut ia_names: HashSet<String> = HashSet::new();
         // values from the other list
         let list_from_ia = ia_policies.get(oid_meta_name).unwrap();
         let list_from_ia_policies = list_from_ia.as_object().unwrap();
-        for name in list_from_ia_policies.keys() {
-            ia_names.insert(name.clone());
-        }
-        // We have all the names from ia
 
         // Step 0, find the operators in ta but not in ia
-        for x in ta_names.difference(&ia_names) {
-            one_metadata_merged.insert(x.clone(), list_of_policies.get(x).unwrap().clone());
+        for (x, operand) in list_of_policies.iter() {
+            if !list_from_ia_policies.contains_key(x) {
+                one_metadata_merged.insert(x.clone(), operand.clone());
+            }
         }
         // Step 1 find the operators in ia but not in ta
-        for x in ia_names.difference(&ta_names) {
-            one_metadata_merged.insert(x.clone(), list_from_ia_policies.get(x).unwrap().clone());
+        for (x, operand) in list_from_ia_policies.iter() {
+            if !list_of_policies.contains_key(x) {
+                one_metadata_merged.insert(x.clone(), operand.clone());
+            }
         }
-        // Step 2 the common operators
-        for operator_name in ta_names.intersection(&ia_names) {
+        // Step 2 the common operators, in a fixed order so that the first error is stable
+        for operator_name in list_of_policies
+            .keys()
+            .filter(|x| list_from_ia_policies.contains_key(*x))
+        {
             // Means both the lists has the same operator
             // We have to deal by each operator here
             let value_from_ta = list_of_policies.get(operator_name).unwrap();
@@ -64,6 +98,7 @@ pub fn merge_policies(
             debug!("From ta: {:?}", value_from_ta);
             debug!("From ia: {:?}", value_from_ia);
             let opname = operator_name.to_string();
+            // check_policy made sure the operands have the right type
             match opname.as_str() {
                 "value" | "default" => {
                     // Both values should be the same
@@ -130,7 +165,9 @@ pub fn merge_policies(
                 }
                 "subset_of" => {
                     let ta_items = get_hashset_from_values(value_from_ta);
+                    let ta_orderd_items = value_from_ta.as_array().unwrap();
                     let ia_items = get_hashset_from_values(value_from_ia);
+                    let ia_orderd_items = value_from_ia.as_array().unwrap();
                     // There can not any item in ia which is not there in ta
                     // T > I
 
@@ -140,7 +177,12 @@ pub fn merge_policies(
                     //ta_items.intersection(&ia_items).collect();
                     //// All good for IA
 
-                    one_metadata_merged.insert("subset_of".to_string(), json!(merged_value));
+                    // Keep the TA order so that the result is stable. The intersection is a
+                    // HashSet, in its order the same policies merged differently on every
+                    // run, and so did the signed statements and diffs made from them.
+                    let result =
+                        get_ordered_array(ta_orderd_items, ia_orderd_items, &merged_value);
+                    one_metadata_merged.insert("subset_of".to_string(), result);
                     //} else {
                     //if n == 1510 {
                     //debug!("TA {:?}\n\nIA {:?}\n\n", ta_items, ia_items);
@@ -179,117 +221,60 @@ pub fn merge_policies(
             }
         }
         // Now we have to verify each of the operator if they are allowed
-        if let Some(value_op) = one_metadata_merged.get("value") {
-            let operator_value_hash = get_hashset_from_values(value_op);
-            // Means we also have add
-            if let Some(add_op) = one_metadata_merged.get("add") {
-                let add_value_hash = get_hashset_from_values(add_op);
-                // https://openid.net/specs/openid-federation-1_0.html#section-6.1.3.1.1-8.1.1
-                if !add_value_hash.is_subset(&operator_value_hash) {
-                    // error
-                    bail!(
-                        r"Subordinate policy merge error: the add must be a subset of the values of value"
-                    );
-                }
-            }
-
-            // Means we also have default
-            if let Some(_default_op) = one_metadata_merged.get("default") {
-                // https://openid.net/specs/openid-federation-1_0.html#section-6.1.3.1.1-8.2.1
-                // Value should not be null
-                if value_op.is_null() {
-                    bail!(r"Subordinate policy merge error: the value must be non-null");
-                }
-            }
-
-            // Means we also have one_of
-            if let Some(one_of_op) = one_metadata_merged.get("one_of") {
-                // https://openid.net/specs/openid-federation-1_0.html#section-6.1.3.1.1-8.3.1
-                // Value must be among the one_of value
-                let one_of_value_hash = get_hashset_from_values(one_of_op);
-                if !operator_value_hash.is_subset(&one_of_value_hash) {
-                    debug!("{:?}", operator_value_hash);
-                    debug!("{:?}", one_of_value_hash);
-                    bail!(
-                        r"Subordinate policy merge error: The value must be among the one_of values"
-                    );
-                }
-            }
-
-            // Means we also have superset_of
-            if let Some(superset_of_op) = one_metadata_merged.get("superset_of") {
-                // https://openid.net/specs/openid-federation-1_0.html#section-6.1.3.1.1-8.5.1
-
-                // Value must be superset_of superset
-                let superset_of_value_hash = get_hashset_from_values(superset_of_op);
-
-                if !superset_of_value_hash.is_subset(&operator_value_hash) {
-                    bail!(
-                        r"Subordinate policy merge error: The value must be a superset of the values of superset_of"
-                    );
-                }
-            }
-            // Means we also have subset_of
-            if let Some(subset_of_op) = one_metadata_merged.get("subset_of") {
-                // https://openid.net/specs/openid-federation-1_0.html#section-6.1.3.1.1-8.4.1
-                // Value must be subset_of subset
-                let subset_of_value_hash = get_hashset_from_values(subset_of_op);
-
-                if !operator_value_hash.is_subset(&subset_of_value_hash) {
-                    bail!(
-                        r"Subordinate policy merge error: The value must be a subset of the values of subset_of"
-                    );
-                }
-            }
-            // Means we also have essential
-            if let Some(essential_op) = one_metadata_merged.get("essential") {
-                //https://openid.net/specs/openid-federation-1_0.html#section-6.1.3.1.1-8.6.1
-                // Value should not be null
-                let es_val = essential_op.as_bool().unwrap();
-                if es_val && value_op.is_null() {
-                    bail!(
-                        r"Subordinate policy merge error: The value must be non-null when essential is true"
-                    );
-                }
-            }
-        }
-        if let Some(add_op) = one_metadata_merged.get("add") {
-            let operator_add_hash = get_hashset_from_values(add_op);
-            // Means we also have subset
-            if let Some(subset_op) = one_metadata_merged.get("subset_of") {
-                let subset_hash = get_hashset_from_values(subset_op);
-                // https://openid.net/specs/openid-federation-1_0.html#section-6.1.3.1.1-8.1.1
-                if !operator_add_hash.is_subset(&subset_hash) {
-                    // error
-                    bail!(
-                        r"Subordinate policy merge error: The values of add must be a subset of the values of subset_of"
-                    );
-                }
-            }
-        }
-        if let Some(subset_op) = one_metadata_merged.get("subset_of") {
-            let operator_subset_hash = get_hashset_from_values(subset_op);
-            // Means we also have superset_of
-            if let Some(superset_op) = one_metadata_merged.get("superset_of") {
-                let superset_hash = get_hashset_from_values(superset_op);
-                // https://openid.net/specs/openid-federation-1_0.html#section-6.1.3.1.5-8.4.1
-                if !superset_hash.is_subset(&operator_subset_hash) {
-                    // error
-                    bail!(
-                        r"Subordinate policy merge error: The values of subset_of must be a superset of the values of superset_of"
-                    );
-                }
-            }
+        if let Some(rule) = broken_combination(&one_metadata_merged) {
+            bail!("Subordinate policy merge error: {}", rule.message);
         }
 
         // We are done for one metadata, merge it to final answer
         merged.insert(oid_meta_name.to_string(), json!(one_metadata_merged));
     }
+    // The ones in IA but not in TA, directly copy over to merged. A subordinate can set
+    // policies for parameters its superior has none for (OpenID Federation section
+    // 6.1.4.2), dropping them would let a leaf skip the policies of its intermediate.
+    for (oid_meta_name, value) in ia_policies.into_iter() {
+        if !ta_policies.contains_key(oid_meta_name) {
+            // check_policy made sure the policy is an object
+            if let Some(rule) = broken_combination(value.as_object().unwrap()) {
+                bail!("Subordinate policy merge error: {}", rule.message);
+            }
+            merged.insert(oid_meta_name.clone(), value.clone());
+        }
+    }
 
     // Now loop
     Ok(merged)
 }
 
+// Merges two metadata_policy claims, which have a policy for each entity type.
+pub fn merge_metadata_policies(
+    superior_in: &Value,
+    subordinate_in: &Value,
+) -> Result<Map<String, Value>> {
+    let (Some(superior), Some(subordinate)) = (superior_in.as_object(), subordinate_in.as_object())
+    else {
+        bail!("Policy error: metadata_policy must be a JSON object");
+    };
+    let empty = json!({});
+    let mut merged: Map<String, Value> = Map::new();
+    for entity_type in superior.keys().chain(subordinate.keys()) {
+        if merged.contains_key(entity_type) {
+            continue;
+        }
+        let superior_policy = superior.get(entity_type).unwrap_or(&empty);
+        let subordinate_policy = subordinate.get(entity_type).unwrap_or(&empty);
+        if !superior_policy.is_object() || !subordinate_policy.is_object() {
+            bail!(
+                "Policy error: policy for {} must be a JSON object",
+                entity_type
+            );
+        }
+        let policy = merge_policies(superior_policy, subordinate_policy)
+            .with_context(|| format!("Failed to merge the {} policy", entity_type))?;
+        merged.insert(entity_type.clone(), Value::Object(policy));
+    }
+    Ok(merged)
+}
+
 pub fn get_ordered_array(
     ta_orderd_items: &[Value],
     ia_orderd_items: &[Value],
@@ -346,6 +331,22 @@ pub fn intersection_of(val: &Value, val2: &Value) -> Option<HashSet<Value>> {
     Some(result.clone())
 }
 
+// Like intersection_of, but keeps the order of the values in val
+pub fn ordered_intersection_of(val: &Value, val2: &Value) -> Value {
+    let v2 = get_hashset_from_values(val2);
+    let mut result: Vec<&Value> = Vec::new();
+    let items = match val {
+        Value::Array(items) => items.iter().collect(),
+        _ => vec![val],
+    };
+    for x in items {
+        if v2.contains(x) && !result.contains(&x) {
+            result.push(x);
+        }
+    }
+    json!(result)
+}
+
 pub fn get_hashset_from_only_names(values: &Value) -> HashSet<Value> {
     let mut hash_set = HashSet::new();
     if values.is_array() {
@@ -367,6 +368,20 @@ pub fn resolve_metadata_policy(
     policy: &Map<String, Value>,
     metadata: &Map<String, Value>,
 ) -> Result<Value> {
+    resolve_and_record(policy, metadata, &mut Vec::new())
+}
+
+// Resolves like resolve_metadata_policy, and pushes to records a (parameter, reason)
+// for every operator as it is applied, in the order they are applied.
+pub(crate) fn resolve_and_record(
+    policy: &Map<String, Value>,
+    metadata: &Map<String, Value>,
+    records: &mut Vec<(String, String)>,
+) -> Result<Value> {
+    check_policy(policy)?;
+    let mut record = |parameter: &str, reason: String| {
+        records.push((parameter.to_string(), reason));
+    };
     debug!("--IN RESOLVE FUNCTION--\n");
     debug!("\npolicy: {:?}", policy);
     debug!("\nmetadata {:?}\n", metadata);
@@ -389,6 +404,7 @@ pub fn resolve_metadata_policy(
         if policy_value.contains_key("value") {
             // THis has highest priority
             let value_data = policy_value.get("value").unwrap();
+            record(metadata_name, value_reason(value_data));
             if !value_data.is_null() {
                 result.insert(metadata_name.to_owned(), value_data.clone());
             }
@@ -400,18 +416,31 @@ pub fn resolve_metadata_policy(
         if let Some(policy_value_data) = policy_value.get("add") {
             debug!("\nWe have ADD in POLICY: {:?}\n", policy_value_data);
             let mut iresult = Vec::new();
-            // we have both add and metadata value
-            let mvalue = metadata_value.as_array().unwrap();
+            // we have both add and metadata value, add can only extend an array
+            let Some(mvalue) = metadata_value.as_array() else {
+                bail!(
+                    "Policy error: {}: add needs an array in the metadata, not {}",
+                    metadata_name,
+                    metadata_value
+                );
+            };
             for v in mvalue.iter() {
                 iresult.push(v);
             }
             debug!("Copied all metadata in iresult: {:?}\n", iresult);
+            let mut added = Vec::new();
             for v in policy_value_data.as_array().unwrap().iter() {
                 // Don't add if we already added
                 if !iresult.contains(&v) {
                     iresult.push(v);
+                    added.push(v);
                 }
             }
+            if added.is_empty() {
+                record(metadata_name, "add: all values already there".to_string());
+            } else {
+                record(metadata_name, format!("add: added {}", json!(added)));
+            }
             debug!("Copied all policy in iresult: {:?}\n", iresult);
 
             internal_result.insert("final".to_string(), json!(iresult.clone()));
@@ -426,6 +455,7 @@ pub fn resolve_metadata_policy(
             if !local_result_flag {
                 internal_result.insert("final".to_string(), metadata_value.clone());
             }
+            record(metadata_name, "default: not used".to_string());
         }
 
         // one_of
@@ -436,6 +466,10 @@ pub fn resolve_metadata_policy(
             if vec_policy.contains(metadata_value) {
                 internal_result.insert("final".to_string(), metadata_value.clone());
                 one_of_flag = true;
+                record(
+                    metadata_name,
+                    format!("one_of: {} is one of {}", metadata_value, policy_value_data),
+                );
             }
             // A single object, can not be a list
             else {
@@ -455,17 +489,13 @@ pub fn resolve_metadata_policy(
                 if is_subset_of(&current_value, policy_value_data) {
                     internal_result.insert("final".to_string(), current_value.clone());
                 }
-                if let Some(middle_data) =
-                    intersection_of(policy_value_data, &current_value.clone())
-                {
-                    if !middle_data.is_empty() {
-                        internal_result.insert("final".to_string(), json!(middle_data));
-                    } else {
-                        let empty_vec: Vec<String> = Vec::new();
-                        // Means nothing common, it should become empty list
-                        internal_result.insert("final".to_string(), json!(empty_vec));
-                    }
-                }
+                // Means nothing common, it becomes an empty list
+                let middle_data = ordered_intersection_of(&current_value, policy_value_data);
+                record(
+                    metadata_name,
+                    subset_reason(&current_value, &middle_data, policy_value_data),
+                );
+                internal_result.insert("final".to_string(), middle_data);
             }
             if let Some(policy_value_data) = policy_value.get("superset_of") {
                 // let vec_policy = policy_value_data.as_array().unwrap();
@@ -477,6 +507,10 @@ pub fn resolve_metadata_policy(
                 debug!("SUPERSET: {:?} and {:?}", policy_value_data, current_value);
                 if is_subset_of(policy_value_data, current_value) {
                     internal_result.insert("final".to_string(), current_value.clone());
+                    record(
+                        metadata_name,
+                        format!("superset_of: contains every value of {}", policy_value_data),
+                    );
                 }
                 // A single object, can not be a list
                 else {
@@ -485,11 +519,26 @@ pub fn resolve_metadata_policy(
                 }
             }
         }
+        if one_of_flag {
+            for operator in ["subset_of", "superset_of"] {
+                if policy_value.contains_key(operator) {
+                    record(
+                        metadata_name,
+                        format!("{}: not applied after one_of", operator),
+                    );
+                }
+            }
+        }
+        if let Some(essential) = policy_value.get("essential") {
+            record(metadata_name, essential_reason(essential));
+        }
+        record_unsupported(&mut record, metadata_name, policy_value);
         debug!("internal_result {:?}\n", internal_result);
-        result.insert(
-            metadata_name.to_string(),
-            internal_result.get("final").unwrap().clone(),
-        );
+        // No operator changed the value (for example only essential), keep the metadata.
+        // essential only checks that the parameter is there (OpenID Federation section
+        // 6.1.3.1.7), and operators we do not know can not change it either.
+        let final_value = internal_result.get("final").unwrap_or(metadata_value);
+        result.insert(metadata_name.to_string(), final_value.clone());
     }
     // Now for the things in policy but not on metadata
     //let policy_hash = get_hashset_from_values(&json!(&policy));
@@ -514,6 +563,7 @@ pub fn resolve_metadata_policy(
             debug!("0metadata: FOUND VALUE IN POLICY");
 
             let value_data = mvalue.get("value").unwrap();
+            record(mkey, value_reason(value_data));
             if !value_data.is_null() {
                 result.insert(mkey.to_owned(), value_data.clone());
             }
@@ -524,14 +574,28 @@ pub fn resolve_metadata_policy(
         let mut new_metadata_flag = false;
         if mvalue.contains_key("add") {
             debug!("0metadata: FOUND ADD IN POLICY");
-            result.insert(mkey.to_owned(), mvalue.get("add").unwrap().clone());
+            let add = mvalue.get("add").unwrap();
+            record(mkey, format!("add: not in the metadata, set to {}", add));
+            result.insert(mkey.to_owned(), add.clone());
             new_metadata_flag = true;
             //continue;
         }
-        if mvalue.contains_key("default") && !new_metadata_flag {
-            debug!("0metadata: FOUND DEFAULT IN POLICY");
-            result.insert(mkey.to_owned(), mvalue.get("default").unwrap().clone());
-            new_metadata_flag = true;
+        if let Some(default) = mvalue.get("default") {
+            if new_metadata_flag {
+                record(mkey, "default: not used".to_string());
+            } else {
+                debug!("0metadata: FOUND DEFAULT IN POLICY");
+                record(
+                    mkey,
+                    format!("default: not in the metadata, set to {}", default),
+                );
+                result.insert(mkey.to_owned(), default.clone());
+                new_metadata_flag = true;
+            }
+        }
+        if mvalue.contains_key("one_of") {
+            // one_of only checks a value from the metadata
+            record(mkey, "one_of: nothing to check".to_string());
         }
 
         let mut empty_subset_found = false;
@@ -540,9 +604,17 @@ pub fn resolve_metadata_policy(
             if new_metadata_flag {
                 let policy_value_data = mvalue.get("subset_of").unwrap();
                 let current_result = result.get(mkey).unwrap();
-                let local_result = intersection_of(current_result, policy_value_data).unwrap();
-                result.insert(mkey.to_owned(), json!(local_result));
+                let local_result = ordered_intersection_of(current_result, policy_value_data);
+                record(
+                    mkey,
+                    subset_reason(current_result, &local_result, policy_value_data),
+                );
+                result.insert(mkey.to_owned(), local_result);
             } else {
+                record(
+                    mkey,
+                    "subset_of: not in the metadata, the parameter stays absent".to_string(),
+                );
                 empty_subset_found = true;
                 new_metadata_flag = true
             }
@@ -581,13 +653,28 @@ pub fn resolve_metadata_policy(
                     //https://openid.net/specs/openid-federation-1_0.html#section-6.1.3.1.6-2
                     bail!("default/add value is not superset_of value")
                 }
+                if empty_subset_found {
+                    record(
+                        mkey,
+                        "superset_of: the parameter is absent, nothing to check".to_string(),
+                    );
+                } else {
+                    record(
+                        mkey,
+                        format!("superset_of: contains every value of {}", policy_value_data),
+                    );
+                }
+            } else {
+                record(mkey, "superset_of: nothing to check".to_string());
             }
             //else {
             //bail!("we have superset_of in policy but no default/add value");
             //}
         }
 
-        if mvalue.contains_key("essential") {
+        // essential: false makes the parameter voluntary (OpenID Federation section
+        // 6.1.3.1.7), only true requires it
+        if mvalue.get("essential") == Some(&Value::Bool(true)) {
             if empty_subset_found {
                 bail!("We have an essential policy but empty subset");
             }
@@ -595,11 +682,94 @@ pub fn resolve_metadata_policy(
                 bail!("We have an essential policy but not metadata");
             }
         }
+        if let Some(essential) = mvalue.get("essential") {
+            record(mkey, essential_reason(essential));
+        }
+        record_unsupported(&mut record, mkey, mvalue);
     }
 
     Ok(json!(result))
 }
 
+fn value_reason(value: &Value) -> String {
+    if value.is_null() {
+        "value: null removes the parameter".to_string()
+    } else {
+        format!("value: set to {}", value)
+    }
+}
+
+fn essential_reason(essential: &Value) -> String {
+    if essential == &Value::Bool(true) {
+        "essential: the parameter must be present".to_string()
+    } else {
+        "essential: the parameter is optional".to_string()
+    }
+}
+
+// before is the value subset_of was applied to, after what it kept
+fn subset_reason(before: &Value, after: &Value, subset_of: &Value) -> String {
+    let kept = get_hashset_from_values(after);
+    let removed: Vec<&Value> = match before {
+        Value::Array(items) => items.iter().filter(|v| !kept.contains(*v)).collect(),
+        _ if !kept.contains(before) => vec![before],
+        _ => Vec::new(),
+    };
+    if removed.is_empty() {
+        format!("subset_of: every value is in {}", subset_of)
+    } else {
+        format!("subset_of: removed {} not in {}", json!(removed), subset_of)
+    }
+}
+
+fn record_unsupported(
+    record: &mut impl FnMut(&str, String),
+    parameter: &str,
+    operators: &Map<String, Value>,
+) {
+    const APPLIED: [&str; 7] = [
+        "value",
+        "add",
+        "default",
+        "one_of",
+        "subset_of",
+        "superset_of",
+        "essential",
+    ];
+    for operator in operators.keys() {
+        if !APPLIED.contains(&operator.as_str()) {
+            record(parameter, format!("{}: not supported, ignored", operator));
+        }
+    }
+}
+
+// Applies a metadata_policy claim to the metadata of an entity, both have a member for
+// each entity type. Policies for entity types without metadata are not used.
+pub fn resolve_metadata_policies(
+    policy: &Map<String, Value>,
+    metadata: &Map<String, Value>,
+) -> Result<Map<String, Value>> {
+    let mut result = Map::new();
+    for (entity_type, entity_metadata) in metadata.iter() {
+        let Some(entity_metadata) = entity_metadata.as_object() else {
+            bail!("Metadata for {} must be a JSON object", entity_type);
+        };
+        let resolved = match policy.get(entity_type) {
+            Some(Value::Object(entity_policy)) => {
+                resolve_metadata_policy(entity_policy, entity_metadata)
+                    .with_context(|| format!("Failed to resolve the {} metadata", entity_type))?
+            }
+            Some(_) => bail!(
+                "Policy error: policy for {} must be a JSON object",
+                entity_type
+            ),
+            None => Value::Object(entity_metadata.clone()),
+        };
+        result.insert(entity_type.clone(), resolved);
+    }
+    Ok(result)
+}
+
 pub fn check_equal(v1: &Value, v2: &Value) -> bool {
     // Check two values are same using unordered sets
     let v1 = v1.as_object().unwrap();
@@ -0,0 +1,328 @@
+// Warnings about metadata policies which are legal, but probably not what was meant, and
+// about combinations of operators which merge_policies rejects.
+use serde_json::{Map, Value, json};
+
+use std::fmt;
+
+use crate::operators::broken_combination;
+
+// Parameters which identify the entity or its endpoints, a superior can not know them
+const ENTITY_SUPPLIED: [&str; 18] = [
+    "jwks",
+    "jwks_uri",
+    "signed_jwks_uri",
+    "redirect_uris",
+    "post_logout_redirect_uris",
+    "initiate_login_uri",
+    "request_uris",
+    "issuer",
+    "authorization_endpoint",
+    "token_endpoint",
+    "userinfo_endpoint",
+    "registration_endpoint",
+    "federation_registration_endpoint",
+    "end_session_endpoint",
+    "introspection_endpoint",
+    "revocation_endpoint",
+    "pushed_authorization_request_endpoint",
+    "federation_fetch_endpoint",
+];
+
+// Parameters whose removal weakens the security of the entity
+const SECURITY_RELEVANT: [&str; 17] = [
+    "jwks",
+    "jwks_uri",
+    "signed_jwks_uri",
+    "redirect_uris",
+    "token_endpoint_auth_method",
+    "token_endpoint_auth_signing_alg",
+    "id_token_signed_response_alg",
+    "userinfo_signed_response_alg",
+    "request_object_signing_alg",
+    "authorization_signed_response_alg",
+    "tls_client_certificate_bound_access_tokens",
+    "dpop_bound_access_tokens",
+    "require_pushed_authorization_requests",
+    "require_signed_request_object",
+    "code_challenge_methods_supported",
+    "token_endpoint_auth_methods_supported",
+    "id_token_signing_alg_values_supported",
+];
+
+// Operators which are never applied when there is a value operator
+const UNREACHABLE_AFTER_VALUE: [&str; 5] = ["add", "default", "one_of", "subset_of", "superset_of"];
+
+// Parameters every entity type can have, OpenID Federation section 5.2
+const COMMON_PARAMETERS: [&str; 10] = [
+    "organization_name",
+    "contacts",
+    "logo_uri",
+    "policy_uri",
+    "information_uri",
+    "organization_uri",
+    "keywords",
+    "jwks",
+    "jwks_uri",
+    "signed_jwks_uri",
+];
+
+const FEDERATION_ENTITY: [&str; 8] = [
+    "federation_fetch_endpoint",
+    "federation_list_endpoint",
+    "federation_resolve_endpoint",
+    "federation_trust_mark_status_endpoint",
+    "federation_trust_mark_list_endpoint",
+    "federation_trust_mark_endpoint",
+    "federation_historical_keys_endpoint",
+    "endpoint_auth_signing_alg_values_supported",
+];
+
+// RFC 7591 with the OpenID Connect and OpenID Federation registration parameters
+const CLIENT: [&str; 48] = [
+    "redirect_uris",
+    "token_endpoint_auth_method",
+    "grant_types",
+    "response_types",
+    "client_name",
+    "client_uri",
+    "logo_uri",
+    "scope",
+    "contacts",
+    "tos_uri",
+    "policy_uri",
+    "jwks_uri",
+    "jwks",
+    "software_id",
+    "software_version",
+    "application_type",
+    "sector_identifier_uri",
+    "subject_type",
+    "id_token_signed_response_alg",
+    "id_token_encrypted_response_alg",
+    "id_token_encrypted_response_enc",
+    "userinfo_signed_response_alg",
+    "userinfo_encrypted_response_alg",
+    "userinfo_encrypted_response_enc",
+    "request_object_signing_alg",
+    "request_object_encryption_alg",
+    "request_object_encryption_enc",
+    "token_endpoint_auth_signing_alg",
+    "default_max_age",
+    "require_auth_time",
+    "default_acr_values",
+    "initiate_login_uri",
+    "request_uris",
+    "post_logout_redirect_uris",
+    "frontchannel_logout_uri",
+    "frontchannel_logout_session_required",
+    "backchannel_logout_uri",
+    "backchannel_logout_session_required",
+    "client_registration_types",
+    "tls_client_auth_subject_dn",
+    "tls_client_certificate_bound_access_tokens",
+    "authorization_signed_response_alg",
+    "authorization_encrypted_response_alg",
+    "authorization_encrypted_response_enc",
+    "dpop_bound_access_tokens",
+    "require_pushed_authorization_requests",
+    "require_signed_request_object",
+    "authorization_details_types",
+];
+
+// RFC 8414 with the OpenID Connect Discovery and OpenID Federation parameters
+const AUTHORIZATION_SERVER: [&str; 54] = [
+    "issuer",
+    "authorization_endpoint",
+    "token_endpoint",
+    "userinfo_endpoint",
+    "jwks_uri",
+    "registration_endpoint",
+    "scopes_supported",
+    "response_types_supported",
+    "response_modes_supported",
+    "grant_types_supported",
+    "acr_values_supported",
+    "subject_types_supported",
+    "id_token_signing_alg_values_supported",
+    "id_token_encryption_alg_values_supported",
+    "id_token_encryption_enc_values_supported",
+    "userinfo_signing_alg_values_supported",
+    "userinfo_encryption_alg_values_supported",
+    "userinfo_encryption_enc_values_supported",
+    "request_object_signing_alg_values_supported",
+    "request_object_encryption_alg_values_supported",
+    "request_object_encryption_enc_values_supported",
+    "token_endpoint_auth_methods_supported",
+    "token_endpoint_auth_signing_alg_values_supported",
+    "display_values_supported",
+    "claim_types_supported",
+    "claims_supported",
+    "service_documentation",
+    "claims_locales_supported",
+    "ui_locales_supported",
+    "claims_parameter_supported",
+    "request_parameter_supported",
+    "request_uri_parameter_supported",
+    "require_request_uri_registration",
+    "op_policy_uri",
+    "op_tos_uri",
+    "end_session_endpoint",
+    "check_session_iframe",
+    "frontchannel_logout_supported",
+    "frontchannel_logout_session_supported",
+    "backchannel_logout_supported",
+    "backchannel_logout_session_supported",
+    "client_registration_types_supported",
+    "federation_registration_endpoint",
+    "request_authentication_methods_supported",
+    "request_authentication_signing_alg_values_supported",
+    "introspection_endpoint",
+    "revocation_endpoint",
+    "pushed_authorization_request_endpoint",
+    "require_pushed_authorization_requests",
+    "code_challenge_methods_supported",
+    "mtls_endpoint_aliases",
+    "tls_client_certificate_bound_access_tokens",
+    "dpop_signing_alg_values_supported",
+    "authorization_response_iss_parameter_supported",
+];
+
+// RFC 9728
+const PROTECTED_RESOURCE: [&str; 14] = [
+    "resource",
+    "authorization_servers",
+    "jwks_uri",
+    "scopes_supported",
+    "bearer_methods_supported",
+    "resource_signing_alg_values_supported",
+    "resource_name",
+    "resource_documentation",
+    "resource_policy_uri",
+    "resource_tos_uri",
+    "tls_client_certificate_bound_access_tokens",
+    "authorization_details_types_supported",
+    "dpop_signing_alg_values_supported",
+    "dpop_bound_access_tokens_required",
+];
+
+// The parameters defined for an entity type, None for entity types we do not know.
+pub fn known_parameters(entity_type: &str) -> Option<Vec<&'static str>> {
+    let specific: &[&str] = match entity_type {
+        "federation_entity" => &FEDERATION_ENTITY,
+        "openid_relying_party" | "oauth_client" => &CLIENT,
+        "openid_provider" | "oauth_authorization_server" => &AUTHORIZATION_SERVER,
+        "oauth_resource" => &PROTECTED_RESOURCE,
+        _ => return None,
+    };
+    Some(COMMON_PARAMETERS.iter().chain(specific).copied().collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    // Stable name of the check, for filtering in tooling
+    pub code: &'static str,
+    pub entity_type: Option<String>,
+    pub parameter: String,
+    pub message: String,
+}
+
+impl Finding {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "code": self.code,
+            "severity": "warning",
+            "entity_type": self.entity_type,
+            "parameter": self.parameter,
+            "message": self.message,
+        })
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.entity_type {
+            Some(entity_type) => write!(
+                f,
+                "warning[{}] {}.{}: {}",
+                self.code, entity_type, self.parameter, self.message
+            ),
+            None => write!(
+                f,
+                "warning[{}] {}: {}",
+                self.code, self.parameter, self.message
+            ),
+        }
+    }
+}
+
+// Lints the policy for one entity type. The entity type is only needed to find
+// parameters which are not defined for it.
+pub fn lint_policy(entity_type: Option<&str>, policy: &Map<String, Value>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let known = entity_type.and_then(known_parameters);
+    for (parameter, operators) in policy.iter() {
+        let Some(operators) = operators.as_object() else {
+            continue;
+        };
+        let mut warn = |code: &'static str, message: String| {
+            findings.push(Finding {
+                code,
+                entity_type: entity_type.map(String::from),
+                parameter: parameter.clone(),
+                message,
+            });
+        };
+
+        if let Some(known) = &known
+            && !known.contains(&parameter.as_str())
+        {
+            warn(
+                "unknown-parameter",
+                format!("{} is not defined for {}", parameter, entity_type.unwrap()),
+            );
+        }
+        if operators.contains_key("default") && ENTITY_SUPPLIED.contains(&parameter.as_str()) {
+            warn(
+                "default-for-entity-parameter",
+                "default for a parameter which the entity itself should supply".to_string(),
+            );
+        }
+        if let Some(rule) = broken_combination(operators) {
+            warn("invalid-combination", rule.message.to_string());
+        }
+        if operators.contains_key("add") && operators.contains_key("one_of") {
+            warn(
+                "add-with-one-of",
+                "add makes a list of values, but one_of only allows a single value".to_string(),
+            );
+        }
+        if let Some(value) = operators.get("value") {
+            if value.is_null() && SECURITY_RELEVANT.contains(&parameter.as_str()) {
+                warn(
+                    "null-value-security-parameter",
+                    "value null removes a security relevant parameter".to_string(),
+                );
+            }
+            for operator in UNREACHABLE_AFTER_VALUE.iter() {
+                if operators.contains_key(*operator) {
+                    warn(
+                        "unreachable-after-value",
+                        format!("{} is never applied as value sets the parameter", operator),
+                    );
+                }
+            }
+        }
+    }
+    findings
+}
+
+// Lints a metadata_policy claim, with a policy for each entity type.
+pub fn lint_metadata_policy(policy: &Map<String, Value>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (entity_type, entity_policy) in policy.iter() {
+        if let Some(entity_policy) = entity_policy.as_object() {
+            findings.extend(lint_policy(Some(entity_type), entity_policy));
+        }
+    }
+    findings
+}
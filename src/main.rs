@@ -1,75 +1,426 @@
-#![allow(unused)]
-use oidfed_metadata_policy::*;
-use serde_json::Value;
-use std::collections::HashSet;
-use std::io;
+use anyhow::{Result, bail};
+use clap::{Parser, Subcommand};
+use oidfed_metadata_policy::batch::batch_resolve;
+use oidfed_metadata_policy::conformance::{
+    VectorFilter, json_report, junit_report, load_test_vectors, run_test_vectors, summary,
+};
+use oidfed_metadata_policy::formats::read_document;
+use oidfed_metadata_policy::generator::{GeneratorConfig, generate_test_vectors};
+use oidfed_metadata_policy::lint::{Finding, lint_metadata_policy, lint_policy};
+use oidfed_metadata_policy::policy_diff::{PolicyChange, diff_metadata_policies, diff_policies};
+use oidfed_metadata_policy::policy_file::{
+    PolicyFile, merge_policy_files, read_policy, resolve_document,
+};
+use oidfed_metadata_policy::remediation::{Suggestion, suggest_fixes, suggest_metadata_fixes};
+use oidfed_metadata_policy::schema::metadata_policy_schema;
+use oidfed_metadata_policy::trace::{ParameterChange, diff_metadata, trace_resolution};
+use serde_json::{Map, Value};
 
-fn main() {
-    let mut stdin = io::stdin();
-    env_logger::init();
-    let data = std::fs::read("data/metadata-policy-test-vectors-2025-02-13.json").unwrap();
-    let data_str = std::str::from_utf8(&data).unwrap();
-    let input: Value = serde_json::from_str(data_str).unwrap();
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(version, about = "OpenID Federation metadata policy tools")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the connect2id metadata policy test vectors
+    Vectors {
+        /// Path to the test vector JSON file
+        file: PathBuf,
+        /// Only run these test numbers, for example 1458, 10-20 or 1,5,10-20
+        #[arg(short = 'n', long)]
+        only: Option<VectorFilter>,
+        /// Write a JSON report to this path
+        #[arg(long)]
+        json_report: Option<PathBuf>,
+        /// Write a JUnit XML report to this path
+        #[arg(long)]
+        junit_report: Option<PathBuf>,
+        /// Print every vector, not only the failures
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Generate test vectors from combinations of operators
+    Generate {
+        /// Largest number of operators in each of the TA and intermediate policies
+        #[arg(long, default_value_t = 2)]
+        max_operators: usize,
+        /// Number of the first generated vector
+        #[arg(long, default_value_t = 1)]
+        first_number: i64,
+        /// Stop after this many vectors
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Write the vectors to this path instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Merge policy files, from the most superior to the most subordinate
+    Merge {
+        /// Policy files, either a policy for one entity type or a document with a
+        /// metadata_policy claim
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Apply a merged policy to the metadata of an entity
+    Resolve {
+        /// The merged policy, either a policy for one entity type or a document with a
+        /// metadata_policy claim
+        #[arg(long)]
+        policy: PathBuf,
+        /// The metadata, either for one entity type or a document with a metadata claim
+        #[arg(long)]
+        metadata: PathBuf,
+        /// Show the parameters which changed
+        #[arg(long)]
+        diff: bool,
+        /// Show every parameter with a policy and what each operator did
+        #[arg(long, conflicts_with = "diff")]
+        trace: bool,
+    },
+    /// Warn about legal but risky constructs in a policy, or in the merge of several
+    Lint {
+        /// Policy files, merged from the most superior to the most subordinate
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Entity type of a policy for one entity type, to find unknown parameters
+        #[arg(long)]
+        entity_type: Option<String>,
+        /// Print the findings as a JSON array
+        #[arg(long)]
+        json: bool,
+        /// Exit with an error when there are findings
+        #[arg(long)]
+        deny_warnings: bool,
+    },
+    /// Show what changed between two versions of a policy, and whether it tightens or
+    /// loosens the policy
+    Diff {
+        /// The old version of the policy
+        old: PathBuf,
+        /// The new version of the policy
+        new: PathBuf,
+        /// Print the changes as a JSON array
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the JSON Schema for metadata_policy and metadata_policy_crit
+    Schema,
+    /// Resolve every leaf in a directory with one policy, or the merge of several
+    Batch {
+        /// Policy files, merged from the most superior to the most subordinate
+        #[arg(long = "policy", required = true)]
+        policies: Vec<PathBuf>,
+        /// Directory of leaf metadata or entity configuration files
+        #[arg(long)]
+        leaves: PathBuf,
+        /// Directory to write the resolved metadata of each leaf to
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+fn print_changes(indent: &str, changes: &[ParameterChange]) {
+    for change in changes.iter() {
+        for line in change.to_string().lines() {
+            println!("{}{}", indent, line);
+        }
+    }
+}
+
+// Resolves the metadata of one entity type with a bare policy, or the metadata claim of
+// an entity configuration with a metadata_policy claim.
+fn suggest_document_fixes(policy: &PolicyFile, metadata: &Value) -> Vec<Suggestion> {
+    match (policy, metadata) {
+        (PolicyFile::Bare(policy), Value::Object(metadata)) => {
+            suggest_fixes(policy.as_object().unwrap(), metadata)
+        }
+        (PolicyFile::Claim(policy), Value::Object(document)) => match document.get("metadata") {
+            Some(Value::Object(metadata)) => {
+                suggest_metadata_fixes(policy.as_object().unwrap(), metadata)
+            }
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn resolve_files(policy_path: &Path, metadata_path: &Path, diff: bool, trace: bool) -> Result<()> {
+    let policy = read_policy(policy_path)?;
+    let metadata = read_document(metadata_path)?;
+    let resolved = match resolve_document(&policy, &metadata) {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            let suggestions = suggest_document_fixes(&policy, &metadata);
+            if !suggestions.is_empty() {
+                eprintln!("To make the metadata pass the policy:");
+                for suggestion in suggestions.iter() {
+                    eprintln!("  {}", suggestion);
+                }
+            }
+            return Err(e.context(format!("Failed to resolve {}", metadata_path.display())));
+        }
+    };
+    println!("{}", serde_json::to_string_pretty(&resolved)?);
+    if !diff && !trace {
+        return Ok(());
+    }
+    match &policy {
+        PolicyFile::Bare(policy) => {
+            print_changes(
+                "",
+                &explain_changes(trace, policy.as_object().unwrap(), &metadata, &resolved)?,
+            );
+        }
+        PolicyFile::Claim(policy) => {
+            let empty = Map::new();
+            for (entity_type, after) in resolved["metadata"].as_object().unwrap().iter() {
+                let entity_policy = policy
+                    .get(entity_type)
+                    .and_then(Value::as_object)
+                    .unwrap_or(&empty);
+                let changes = explain_changes(
+                    trace,
+                    entity_policy,
+                    &metadata["metadata"][entity_type],
+                    after,
+                )?;
+                if !changes.is_empty() {
+                    println!("{}:", entity_type);
+                    print_changes("  ", &changes);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// With trace the reasons come from applying the policy to before again
+fn explain_changes(
+    trace: bool,
+    policy: &Map<String, Value>,
+    before: &Value,
+    after: &Value,
+) -> Result<Vec<ParameterChange>> {
+    let before = before.as_object().unwrap();
+    if trace {
+        trace_resolution(policy, before)
+    } else {
+        Ok(diff_metadata(before, after.as_object().unwrap()))
+    }
+}
+
+// Lints every policy file on its own, then their merge for what only the merge shows.
+// The files are linted before they are merged, as merging fails on the invalid operator
+// combinations the lint reports. The error of reading or merging comes with the findings
+// made before it.
+fn lint_files(
+    files: &[PathBuf],
+    entity_type: Option<&str>,
+) -> (Vec<Finding>, Option<anyhow::Error>) {
+    let mut findings = Vec::new();
+    for path in files.iter() {
+        match read_policy(path) {
+            Ok(policy) => add_findings(&mut findings, lint_policy_file(&policy, entity_type)),
+            Err(e) => return (findings, Some(e)),
+        }
+    }
+    match merge_policy_files(files) {
+        Ok(merged) => {
+            add_findings(&mut findings, lint_policy_file(&merged, entity_type));
+            (findings, None)
+        }
+        Err(e) => (findings, Some(e)),
+    }
+}
 
-    for one_test in input.as_array().unwrap().iter() {
-        let input_map = one_test.as_object().unwrap();
-        let n = &input_map["n"];
-        // For one test
-        //if n.as_i64().unwrap() != 1458 {
-        //continue;
-        //}
-        eprintln!("Running {}", n);
-        let merged = merge_policies(&input_map["TA"], &input_map["INT"]);
-        match merged {
-            Ok(m) => {
-                eprintln!("Merged answer: {:?}\n\n", m);
-                let expected = input_map.get("merged");
-                match expected {
-                    Some(exp) => {
-                        let final_exp = exp.as_object().unwrap();
+fn lint_policy_file(policy: &PolicyFile, entity_type: Option<&str>) -> Vec<Finding> {
+    match policy {
+        PolicyFile::Claim(policy) => lint_metadata_policy(policy.as_object().unwrap()),
+        PolicyFile::Bare(policy) => lint_policy(entity_type, policy.as_object().unwrap()),
+    }
+}
 
-                        if (*final_exp != m) {
-                            eprintln!("Expected answer: {:?}\n", final_exp);
-                            panic!("Failed");
-                        } else {
-                            // Merge worked, now we should apply the input to the merged answer
-                            let metadata = input_map.get("metadata").unwrap().as_object().unwrap();
-                            let result = resolve_metadata_policy(&m, metadata);
-                            if result.is_err() {
-                                let expected = input_map.get("error");
-                                match expected {
-                                    Some(exp) => {
-                                        eprintln!("Received error in policy as expected\n");
-                                        continue;
-                                    }
-                                    None => panic!("Missing error output at {}", n),
-                                }
-                                panic!("{}", result.err().unwrap());
-                            }
-                            let result = result.ok().unwrap();
-                            let resolved = input_map.get("resolved").unwrap();
-                            eprintln!(
-                                "Result: {:?}  and expected_result: {:?}\n\n",
-                                result, resolved
-                            );
-                            if !check_equal(resolved, &result) {
-                                panic!("Failed");
-                            }
+// A finding of several files, or of a file and the merge, is reported once
+fn add_findings(findings: &mut Vec<Finding>, found: Vec<Finding>) {
+    for finding in found {
+        if !findings.contains(&finding) {
+            findings.push(finding);
+        }
+    }
+}
 
-                            // Read a single byte and discard
-                            //let _ = stdin.read(&mut [0u8]).unwrap();
-                        }
-                    }
-                    None => panic!("Missing merged output at {}", n),
+fn diff_files(old_path: &Path, new_path: &Path) -> Result<Vec<PolicyChange>> {
+    let changes = match (read_policy(old_path)?, read_policy(new_path)?) {
+        (PolicyFile::Bare(old), PolicyFile::Bare(new)) => {
+            diff_policies(old.as_object().unwrap(), new.as_object().unwrap())
+        }
+        (PolicyFile::Claim(old), PolicyFile::Claim(new)) => {
+            diff_metadata_policies(old.as_object().unwrap(), new.as_object().unwrap())
+        }
+        _ => bail!(
+            "Can not diff {} and {}: a bare policy and a metadata_policy claim can not be compared",
+            old_path.display(),
+            new_path.display()
+        ),
+    };
+    Ok(changes)
+}
+
+fn main() -> Result<ExitCode> {
+    env_logger::init();
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Vectors {
+            file,
+            only,
+            json_report: json_path,
+            junit_report: junit_path,
+            verbose,
+        } => {
+            let vectors = load_test_vectors(&file)?;
+            let results = run_test_vectors(&vectors, only.as_ref());
+            for r in results.iter() {
+                match &r.failure {
+                    Some(message) => println!("FAIL {}: {}", r.n, message),
+                    None if verbose => println!("ok   {}", r.n),
+                    None => (),
                 }
             }
+            println!("{}", summary(&results));
+
+            let source = file.display().to_string();
+            if let Some(path) = json_path {
+                let report = json_report(&source, &results);
+                std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+            }
+            if let Some(path) = junit_path {
+                std::fs::write(path, junit_report(&source, &results))?;
+            }
+            if results.iter().all(|r| r.passed()) {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::FAILURE)
+            }
+        }
+        Command::Generate {
+            max_operators,
+            first_number,
+            limit,
+            output,
+        } => {
+            let config = GeneratorConfig {
+                max_operators,
+                first_number,
+                limit,
+                ..Default::default()
+            };
+            let vectors = generate_test_vectors(&config);
+            let text = serde_json::to_string_pretty(&vectors)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, text)?;
+                    eprintln!("Wrote {} vectors to {}", vectors.len(), path.display());
+                }
+                None => println!("{}", text),
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Resolve {
+            policy,
+            metadata,
+            diff,
+            trace,
+        } => match resolve_files(&policy, &metadata, diff, trace) {
+            Ok(()) => Ok(ExitCode::SUCCESS),
             Err(e) => {
-                let expected = input_map.get("error");
-                match expected {
-                    Some(exp) => (eprintln!("Received error in policy as expected\n")),
-                    None => panic!("Missing error output at {}", n),
+                eprintln!("Error: {:#}", e);
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Command::Lint {
+            files,
+            entity_type,
+            json,
+            deny_warnings,
+        } => {
+            let (findings, error) = lint_files(&files, entity_type.as_deref());
+            if json {
+                let findings: Vec<Value> = findings.iter().map(Finding::to_json).collect();
+                println!("{}", serde_json::to_string_pretty(&findings)?);
+            } else {
+                for finding in findings.iter() {
+                    println!("{}", finding);
+                }
+            }
+            if let Some(e) = error {
+                eprintln!("Error: {:#}", e);
+                Ok(ExitCode::FAILURE)
+            } else if deny_warnings && !findings.is_empty() {
+                Ok(ExitCode::FAILURE)
+            } else {
+                Ok(ExitCode::SUCCESS)
+            }
+        }
+        Command::Diff { old, new, json } => {
+            let changes = match diff_files(&old, &new) {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("Error: {:#}", e);
+                    return Ok(ExitCode::FAILURE);
+                }
+            };
+            if json {
+                let changes: Vec<Value> = changes.iter().map(PolicyChange::to_json).collect();
+                println!("{}", serde_json::to_string_pretty(&changes)?);
+            } else {
+                for change in changes.iter() {
+                    println!("{}", change);
                 }
             }
+            Ok(ExitCode::SUCCESS)
         }
+        Command::Schema => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&metadata_policy_schema())?
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Batch {
+            policies,
+            leaves,
+            output,
+        } => match merge_policy_files(&policies)
+            .and_then(|policy| batch_resolve(&policy, &leaves, output.as_deref()))
+        {
+            Ok(report) => {
+                println!("{}", report);
+                if report.failed() == 0 {
+                    Ok(ExitCode::SUCCESS)
+                } else {
+                    Ok(ExitCode::FAILURE)
+                }
+            }
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Command::Merge { files } => match merge_policy_files(&files) {
+            Ok(merged) => {
+                println!("{}", serde_json::to_string_pretty(&merged.into_document())?);
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                Ok(ExitCode::FAILURE)
+            }
+        },
     }
 }
@@ -0,0 +1,196 @@
+// The standard policy operators of OpenID Federation section 6.1.3.1, and the type of
+// the operand each one takes. merge_policies and resolve_metadata_policy check policies
+// against these before using them, and schema generates the JSON Schema from them.
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+
+use crate::get_hashset_from_values;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    // Any JSON value, null included
+    Any,
+    // Any JSON value but null
+    NonNull,
+    // A JSON array
+    Array,
+    Boolean,
+}
+
+impl Operand {
+    pub fn accepts(&self, value: &Value) -> bool {
+        match self {
+            Operand::Any => true,
+            Operand::NonNull => !value.is_null(),
+            Operand::Array => value.is_array(),
+            Operand::Boolean => value.is_boolean(),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Operand::Any => "any JSON value",
+            Operand::NonNull => "a non-null JSON value",
+            Operand::Array => "an array",
+            Operand::Boolean => "a boolean",
+        }
+    }
+}
+
+pub struct Operator {
+    pub name: &'static str,
+    pub operand: Operand,
+    pub description: &'static str,
+}
+
+pub const OPERATORS: [Operator; 7] = [
+    Operator {
+        name: "value",
+        operand: Operand::Any,
+        description: "Sets the parameter to this value, null removes the parameter",
+    },
+    Operator {
+        name: "add",
+        operand: Operand::Array,
+        description: "Adds these values to the parameter",
+    },
+    Operator {
+        name: "default",
+        operand: Operand::NonNull,
+        description: "Sets the parameter to this value when it is missing",
+    },
+    Operator {
+        name: "one_of",
+        operand: Operand::Array,
+        description: "The parameter must be one of these values",
+    },
+    Operator {
+        name: "subset_of",
+        operand: Operand::Array,
+        description: "Removes the values of the parameter which are not in this list",
+    },
+    Operator {
+        name: "superset_of",
+        operand: Operand::Array,
+        description: "The parameter must contain all of these values",
+    },
+    Operator {
+        name: "essential",
+        operand: Operand::Boolean,
+        description: "When true, the parameter must be present",
+    },
+];
+
+pub fn find_operator(name: &str) -> Option<&'static Operator> {
+    OPERATORS.iter().find(|o| o.name == name)
+}
+
+// Checks the operand of a standard operator, other operators are not checked.
+pub fn check_operand(operator: &str, operand: &Value) -> Result<()> {
+    if let Some(definition) = find_operator(operator)
+        && !definition.operand.accepts(operand)
+    {
+        bail!(
+            "{} must be {}, not {}",
+            operator,
+            definition.operand.description(),
+            operand
+        );
+    }
+    Ok(())
+}
+
+// Checks that every parameter of a policy for one entity type has an object of operators,
+// and that the standard operators have operands of the right type.
+pub fn check_policy(policy: &Map<String, Value>) -> Result<()> {
+    for (parameter, operators) in policy.iter() {
+        let Some(operators) = operators.as_object() else {
+            bail!(
+                "Policy error: the policy for {} must be a JSON object",
+                parameter
+            );
+        };
+        for (operator, operand) in operators.iter() {
+            if let Err(e) = check_operand(operator, operand) {
+                bail!("Policy error: {}: {}", parameter, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+// What the operands of two operators of the same parameter must satisfy, OpenID
+// Federation section 6.1.3.1. Any combination without a rule is allowed.
+pub struct CombinationRule {
+    pub operator: &'static str,
+    pub other: &'static str,
+    // Called with the operand of operator, then the operand of other
+    pub holds: fn(&Value, &Value) -> bool,
+    pub message: &'static str,
+}
+
+// merge_policies, lint and builder::PolicyBuilder all check policies against these.
+pub const COMBINATION_RULES: [CombinationRule; 8] = [
+    CombinationRule {
+        operator: "value",
+        other: "add",
+        holds: |value, add| is_subset(add, value),
+        message: "the add must be a subset of the values of value",
+    },
+    CombinationRule {
+        operator: "value",
+        other: "default",
+        holds: |value, _| !value.is_null(),
+        message: "the value must be non-null when there is a default",
+    },
+    CombinationRule {
+        operator: "value",
+        other: "one_of",
+        holds: |value, one_of| is_subset(value, one_of),
+        message: "the value must be among the one_of values",
+    },
+    CombinationRule {
+        operator: "value",
+        other: "superset_of",
+        holds: |value, superset_of| is_subset(superset_of, value),
+        message: "the value must be a superset of the values of superset_of",
+    },
+    CombinationRule {
+        operator: "value",
+        other: "subset_of",
+        holds: |value, subset_of| is_subset(value, subset_of),
+        message: "the value must be a subset of the values of subset_of",
+    },
+    CombinationRule {
+        operator: "value",
+        other: "essential",
+        holds: |value, essential| !(value.is_null() && essential == &Value::Bool(true)),
+        message: "the value must be non-null when essential is true",
+    },
+    CombinationRule {
+        operator: "add",
+        other: "subset_of",
+        holds: |add, subset_of| is_subset(add, subset_of),
+        message: "the values of add must be a subset of the values of subset_of",
+    },
+    CombinationRule {
+        operator: "subset_of",
+        other: "superset_of",
+        holds: |subset_of, superset_of| is_subset(superset_of, subset_of),
+        message: "the values of subset_of must be a superset of the values of superset_of",
+    },
+];
+
+fn is_subset(values: &Value, of: &Value) -> bool {
+    get_hashset_from_values(values).is_subset(&get_hashset_from_values(of))
+}
+
+// The first rule which the operators of one parameter break.
+pub fn broken_combination(operators: &Map<String, Value>) -> Option<&'static CombinationRule> {
+    COMBINATION_RULES.iter().find(|rule| {
+        match (operators.get(rule.operator), operators.get(rule.other)) {
+            (Some(operand), Some(other)) => !(rule.holds)(operand, other),
+            _ => false,
+        }
+    })
+}
@@ -0,0 +1,263 @@
+// Semantic diff of two versions of a metadata policy, for reviewing policy changes.
+//
+// Every change is one operator of one parameter, and says whether it makes the policy
+// stricter (tightens) or more permissive (loosens) for the subordinates.
+use serde_json::{Map, Value, json};
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    Tightens,
+    Loosens,
+    // Neither, for example a changed default
+    Neutral,
+}
+
+impl Effect {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Effect::Tightens => "tightens",
+            Effect::Loosens => "loosens",
+            Effect::Neutral => "neutral",
+        }
+    }
+
+    fn reverse(self) -> Effect {
+        match self {
+            Effect::Tightens => Effect::Loosens,
+            Effect::Loosens => Effect::Tightens,
+            Effect::Neutral => Effect::Neutral,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    OperatorAdded(Value),
+    OperatorRemoved(Value),
+    ValueChanged { old: Value, new: Value },
+    // Values added to or removed from one_of, subset_of, superset_of or add
+    ValuesAdded(Vec<Value>),
+    ValuesRemoved(Vec<Value>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyChange {
+    // None for the policy of a single entity type
+    pub entity_type: Option<String>,
+    pub parameter: String,
+    pub operator: String,
+    pub kind: ChangeKind,
+    pub effect: Effect,
+}
+
+impl PolicyChange {
+    pub fn to_json(&self) -> Value {
+        let (kind, details) = match &self.kind {
+            ChangeKind::OperatorAdded(v) => ("operator_added", json!({ "value": v })),
+            ChangeKind::OperatorRemoved(v) => ("operator_removed", json!({ "value": v })),
+            ChangeKind::ValueChanged { old, new } => {
+                ("value_changed", json!({ "old": old, "new": new }))
+            }
+            ChangeKind::ValuesAdded(v) => ("values_added", json!({ "values": v })),
+            ChangeKind::ValuesRemoved(v) => ("values_removed", json!({ "values": v })),
+        };
+        let mut result = json!({
+            "entity_type": self.entity_type,
+            "parameter": self.parameter,
+            "operator": self.operator,
+            "kind": kind,
+            "effect": self.effect.as_str(),
+        });
+        for (key, value) in details.as_object().unwrap().iter() {
+            result[key] = value.clone();
+        }
+        result
+    }
+}
+
+impl fmt::Display for PolicyChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(entity_type) = &self.entity_type {
+            write!(f, "{}.", entity_type)?;
+        }
+        write!(f, "{} {}: ", self.parameter, self.operator)?;
+        match &self.kind {
+            ChangeKind::OperatorAdded(v) => write!(f, "added {}", v)?,
+            ChangeKind::OperatorRemoved(v) => write!(f, "removed {}", v)?,
+            ChangeKind::ValueChanged { old, new } => write!(f, "{} -> {}", old, new)?,
+            ChangeKind::ValuesAdded(v) => write!(f, "added values {}", json!(v))?,
+            ChangeKind::ValuesRemoved(v) => write!(f, "removed values {}", json!(v))?,
+        }
+        write!(f, " ({})", self.effect.as_str())
+    }
+}
+
+// What adding the operator does to the policy
+fn operator_effect(operator: &str, value: &Value) -> Effect {
+    match operator {
+        "value" | "add" | "one_of" | "subset_of" | "superset_of" => Effect::Tightens,
+        "essential" if value == &Value::Bool(true) => Effect::Tightens,
+        _ => Effect::Neutral,
+    }
+}
+
+// What adding values to the operator does to the policy
+fn values_added_effect(operator: &str) -> Effect {
+    match operator {
+        "one_of" | "subset_of" => Effect::Loosens,
+        "superset_of" | "add" => Effect::Tightens,
+        _ => Effect::Neutral,
+    }
+}
+
+fn ordered_difference(values: &[Value], other: &[Value]) -> Vec<Value> {
+    values
+        .iter()
+        .filter(|v| !other.contains(v))
+        .cloned()
+        .collect()
+}
+
+fn diff_operator(operator: &str, old: &Value, new: &Value) -> Vec<(ChangeKind, Effect)> {
+    if old == new {
+        return Vec::new();
+    }
+    let set_operator = ["one_of", "subset_of", "superset_of", "add"].contains(&operator);
+    match (old, new) {
+        (Value::Array(old_values), Value::Array(new_values)) if set_operator => {
+            let mut changes = Vec::new();
+            let added = ordered_difference(new_values, old_values);
+            if !added.is_empty() {
+                changes.push((
+                    ChangeKind::ValuesAdded(added),
+                    values_added_effect(operator),
+                ));
+            }
+            let removed = ordered_difference(old_values, new_values);
+            if !removed.is_empty() {
+                changes.push((
+                    ChangeKind::ValuesRemoved(removed),
+                    values_added_effect(operator).reverse(),
+                ));
+            }
+            // Only the order changed
+            changes
+        }
+        _ => {
+            let effect = match operator {
+                "essential" => {
+                    if new == &Value::Bool(true) {
+                        Effect::Tightens
+                    } else {
+                        Effect::Loosens
+                    }
+                }
+                _ => Effect::Neutral,
+            };
+            vec![(
+                ChangeKind::ValueChanged {
+                    old: old.clone(),
+                    new: new.clone(),
+                },
+                effect,
+            )]
+        }
+    }
+}
+
+fn diff_parameter(
+    entity_type: Option<&str>,
+    parameter: &str,
+    old: &Map<String, Value>,
+    new: &Map<String, Value>,
+    changes: &mut Vec<PolicyChange>,
+) {
+    let mut push = |operator: &str, kind: ChangeKind, effect: Effect| {
+        changes.push(PolicyChange {
+            entity_type: entity_type.map(String::from),
+            parameter: parameter.to_string(),
+            operator: operator.to_string(),
+            kind,
+            effect,
+        })
+    };
+    for (operator, old_value) in old.iter() {
+        match new.get(operator) {
+            Some(new_value) => {
+                for (kind, effect) in diff_operator(operator, old_value, new_value) {
+                    push(operator, kind, effect);
+                }
+            }
+            None => push(
+                operator,
+                ChangeKind::OperatorRemoved(old_value.clone()),
+                operator_effect(operator, old_value).reverse(),
+            ),
+        }
+    }
+    for (operator, new_value) in new.iter() {
+        if !old.contains_key(operator) {
+            push(
+                operator,
+                ChangeKind::OperatorAdded(new_value.clone()),
+                operator_effect(operator, new_value),
+            );
+        }
+    }
+}
+
+fn diff_entity_policy(
+    entity_type: Option<&str>,
+    old: &Map<String, Value>,
+    new: &Map<String, Value>,
+    changes: &mut Vec<PolicyChange>,
+) {
+    let empty = Map::new();
+    let parameters = old
+        .keys()
+        .chain(new.keys().filter(|k| !old.contains_key(*k)));
+    for parameter in parameters {
+        let old_ops = old
+            .get(parameter)
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+        let new_ops = new
+            .get(parameter)
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+        diff_parameter(entity_type, parameter, old_ops, new_ops, changes);
+    }
+}
+
+// Diff of two policies for one entity type.
+pub fn diff_policies(old: &Map<String, Value>, new: &Map<String, Value>) -> Vec<PolicyChange> {
+    let mut changes = Vec::new();
+    diff_entity_policy(None, old, new, &mut changes);
+    changes
+}
+
+// Diff of two metadata_policy claims, with a policy for each entity type.
+pub fn diff_metadata_policies(
+    old: &Map<String, Value>,
+    new: &Map<String, Value>,
+) -> Vec<PolicyChange> {
+    let empty = Map::new();
+    let mut changes = Vec::new();
+    let entity_types = old
+        .keys()
+        .chain(new.keys().filter(|k| !old.contains_key(*k)));
+    for entity_type in entity_types {
+        let old_policy = old
+            .get(entity_type)
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+        let new_policy = new
+            .get(entity_type)
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+        diff_entity_policy(Some(entity_type), old_policy, new_policy, &mut changes);
+    }
+    changes
+}
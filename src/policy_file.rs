@@ -0,0 +1,125 @@
+// Policy files as the command line reads them: either a bare policy for one entity type,
+// or a document with a metadata_policy claim covering several entity types.
+use anyhow::{Context, Result, bail};
+use serde_json::{Value, json};
+
+use std::path::{Path, PathBuf};
+
+use crate::formats::read_document;
+use crate::{
+    merge_metadata_policies, merge_policies, resolve_metadata_policies, resolve_metadata_policy,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyFile {
+    // {"grant_types": {"subset_of": [...]}, ...}
+    Bare(Value),
+    // {"metadata_policy": {"openid_relying_party": {...}, ...}, ...}
+    Claim(Value),
+}
+
+impl PolicyFile {
+    // The policy as a document again, the claim wrapped in metadata_policy
+    pub fn into_document(self) -> Value {
+        match self {
+            PolicyFile::Bare(policy) => policy,
+            PolicyFile::Claim(policy) => json!({ "metadata_policy": policy }),
+        }
+    }
+}
+
+pub fn read_policy(path: &Path) -> Result<PolicyFile> {
+    policy_from_document(read_document(path)?)
+        .with_context(|| format!("{} is not a valid metadata policy", path.display()))
+}
+
+// Tells a bare policy from a document with a metadata_policy claim, and checks that it
+// has the shape of a policy.
+pub fn policy_from_document(value: Value) -> Result<PolicyFile> {
+    let Some(map) = value.as_object() else {
+        bail!("Policy error: the document must be a JSON object");
+    };
+    let (policy, depth) = match map.get("metadata_policy") {
+        Some(claim) => (claim, 3),
+        None => (&value, 2),
+    };
+    if !is_nested_object(policy, depth) {
+        bail!("Policy error: every parameter must have a JSON object of operators");
+    }
+    match map.get("metadata_policy") {
+        Some(claim) => Ok(PolicyFile::Claim(claim.clone())),
+        None => Ok(PolicyFile::Bare(value)),
+    }
+}
+
+// Checks that value is an object with `depth` levels of objects below it
+fn is_nested_object(value: &Value, depth: usize) -> bool {
+    match value.as_object() {
+        Some(map) => depth <= 1 || map.values().all(|v| is_nested_object(v, depth - 1)),
+        None => false,
+    }
+}
+
+// Merges policies in order, the superior first. All must be bare policies or all
+// metadata_policy claims. A single policy is only checked.
+pub fn merge_policy_files(files: &[PathBuf]) -> Result<PolicyFile> {
+    let mut merged: Option<PolicyFile> = None;
+    for path in files.iter() {
+        let policy = read_policy(path)?;
+        merged = Some(match merged {
+            None => {
+                check_policy_file(&policy)
+                    .with_context(|| format!("Invalid policy in {}", path.display()))?;
+                policy
+            }
+            Some(superior) => merge_policy_pair(superior, policy)
+                .with_context(|| format!("Failed to merge {}", path.display()))?,
+        });
+    }
+    merged.context("No policy files given")
+}
+
+// Merges a subordinate policy into the superior one
+pub fn merge_policy_pair(superior: PolicyFile, subordinate: PolicyFile) -> Result<PolicyFile> {
+    match (superior, subordinate) {
+        (PolicyFile::Bare(superior), PolicyFile::Bare(subordinate)) => Ok(PolicyFile::Bare(
+            Value::Object(merge_policies(&superior, &subordinate)?),
+        )),
+        (PolicyFile::Claim(superior), PolicyFile::Claim(subordinate)) => Ok(PolicyFile::Claim(
+            Value::Object(merge_metadata_policies(&superior, &subordinate)?),
+        )),
+        _ => bail!("Policy error: bare policies and metadata_policy claims can not be mixed"),
+    }
+}
+
+// The operators of a policy are valid on their own, like in a merge with no superior
+fn check_policy_file(policy: &PolicyFile) -> Result<()> {
+    let empty = match policy {
+        PolicyFile::Bare(_) => PolicyFile::Bare(json!({})),
+        PolicyFile::Claim(_) => PolicyFile::Claim(json!({})),
+    };
+    merge_policy_pair(empty, policy.clone()).map(|_| ())
+}
+
+// Applies the policy to a document: the metadata of one entity type for a bare policy,
+// or a document with a metadata claim for a metadata_policy claim.
+pub fn resolve_document(policy: &PolicyFile, metadata: &Value) -> Result<Value> {
+    let Some(metadata_map) = metadata.as_object() else {
+        bail!("Metadata must be an object");
+    };
+    match policy {
+        PolicyFile::Bare(policy) => {
+            if metadata_map.contains_key("metadata") {
+                bail!("A policy for one entity type needs the metadata of one entity type");
+            }
+            resolve_metadata_policy(policy.as_object().unwrap(), metadata_map)
+        }
+        PolicyFile::Claim(policy) => {
+            let Some(Value::Object(entity_metadata)) = metadata_map.get("metadata") else {
+                bail!("A metadata_policy claim needs a document with a metadata claim");
+            };
+            let resolved = resolve_metadata_policies(policy.as_object().unwrap(), entity_metadata)?;
+            Ok(json!({ "metadata": resolved }))
+        }
+    }
+}
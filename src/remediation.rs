@@ -0,0 +1,259 @@
+// Suggests the smallest edits to metadata which resolve_metadata_policy rejects, so the
+// operator of a leaf knows what to change.
+//
+// Every parameter is resolved on its own, and every suggestion is checked by resolving
+// the edited parameter again.
+use serde_json::{Map, Value, json};
+
+use std::fmt;
+
+use crate::resolve_metadata_policy;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fix {
+    // Set the parameter to this value, or supply it when it is missing
+    SetValue(Value),
+    // Add these values to the list
+    AddValues(Vec<Value>),
+    // The parameter is essential, but the policy does not say which value it needs
+    SupplyParameter,
+    // No change to the metadata passes the policy, the policy itself has to change
+    Unfixable(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    // None for the metadata of a single entity type
+    pub entity_type: Option<String>,
+    pub parameter: String,
+    // The error from resolve_metadata_policy
+    pub problem: String,
+    pub fix: Fix,
+}
+
+impl Suggestion {
+    pub fn to_json(&self) -> Value {
+        let mut result = json!({
+            "entity_type": self.entity_type,
+            "parameter": self.parameter,
+            "problem": self.problem,
+        });
+        let (fix, value) = match &self.fix {
+            Fix::SetValue(v) => ("set_value", Some(v.clone())),
+            Fix::AddValues(v) => ("add_values", Some(json!(v))),
+            Fix::SupplyParameter => ("supply_parameter", None),
+            Fix::Unfixable(reason) => ("unfixable", Some(json!(reason))),
+        };
+        result["fix"] = json!(fix);
+        if let Some(value) = value {
+            result["value"] = value;
+        }
+        result
+    }
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(entity_type) = &self.entity_type {
+            write!(f, "{}.", entity_type)?;
+        }
+        write!(f, "{}: {}, ", self.parameter, self.problem)?;
+        match &self.fix {
+            Fix::SetValue(v) => write!(f, "set it to {}", v),
+            Fix::AddValues(v) => write!(f, "add {}", json!(v)),
+            Fix::SupplyParameter => write!(f, "supply a value"),
+            Fix::Unfixable(reason) => write!(f, "can not be fixed in the metadata: {}", reason),
+        }
+    }
+}
+
+// The metadata with the suggested edits applied. Unfixable suggestions are skipped.
+pub fn apply_fixes(
+    metadata: &Map<String, Value>,
+    suggestions: &[Suggestion],
+) -> Map<String, Value> {
+    let mut result = metadata.clone();
+    for suggestion in suggestions.iter() {
+        apply_fix(&mut result, &suggestion.parameter, &suggestion.fix);
+    }
+    result
+}
+
+fn apply_fix(metadata: &mut Map<String, Value>, parameter: &str, fix: &Fix) {
+    match fix {
+        Fix::SetValue(value) => {
+            metadata.insert(parameter.to_string(), value.clone());
+        }
+        Fix::AddValues(values) => {
+            let mut list = match metadata.get(parameter) {
+                Some(Value::Array(current)) => current.clone(),
+                Some(current) => vec![current.clone()],
+                None => Vec::new(),
+            };
+            for value in values.iter() {
+                if !list.contains(value) {
+                    list.push(value.clone());
+                }
+            }
+            metadata.insert(parameter.to_string(), Value::Array(list));
+        }
+        Fix::SupplyParameter | Fix::Unfixable(_) => (),
+    }
+}
+
+// Suggestions for the metadata of one entity type, one for each parameter which fails.
+pub fn suggest_fixes(
+    policy: &Map<String, Value>,
+    metadata: &Map<String, Value>,
+) -> Vec<Suggestion> {
+    suggest_entity_fixes(None, policy, metadata)
+}
+
+// Suggestions for a metadata claim, with the policies of a metadata_policy claim.
+pub fn suggest_metadata_fixes(
+    policy: &Map<String, Value>,
+    metadata: &Map<String, Value>,
+) -> Vec<Suggestion> {
+    let mut suggestions = Vec::new();
+    for (entity_type, entity_metadata) in metadata.iter() {
+        let (Some(entity_policy), Some(entity_metadata)) = (
+            policy.get(entity_type).and_then(Value::as_object),
+            entity_metadata.as_object(),
+        ) else {
+            continue;
+        };
+        suggestions.extend(suggest_entity_fixes(
+            Some(entity_type),
+            entity_policy,
+            entity_metadata,
+        ));
+    }
+    suggestions
+}
+
+fn suggest_entity_fixes(
+    entity_type: Option<&str>,
+    policy: &Map<String, Value>,
+    metadata: &Map<String, Value>,
+) -> Vec<Suggestion> {
+    let mut suggestions = Vec::new();
+    for (parameter, operators) in policy.iter() {
+        let Some(operators) = operators.as_object() else {
+            continue;
+        };
+        let current = metadata.get(parameter);
+        let Err(problem) = resolve_parameter(parameter, operators, current) else {
+            continue;
+        };
+        let mut fix = match current {
+            Some(value) => fix_present(operators, value),
+            None => fix_missing(operators),
+        };
+        // Check the fix, the operators can contradict each other
+        if matches!(fix, Fix::SetValue(_) | Fix::AddValues(_)) {
+            let mut fixed = Map::new();
+            if let Some(value) = current {
+                fixed.insert(parameter.clone(), value.clone());
+            }
+            apply_fix(&mut fixed, parameter, &fix);
+            if let Err(e) = resolve_parameter(parameter, operators, fixed.get(parameter)) {
+                fix = Fix::Unfixable(format!(
+                    "the policy rejects {} too: {}",
+                    fixed[parameter], e
+                ));
+            }
+        }
+        suggestions.push(Suggestion {
+            entity_type: entity_type.map(String::from),
+            parameter: parameter.clone(),
+            problem,
+            fix,
+        });
+    }
+    suggestions
+}
+
+fn resolve_parameter(
+    parameter: &str,
+    operators: &Map<String, Value>,
+    value: Option<&Value>,
+) -> Result<(), String> {
+    let mut policy = Map::new();
+    policy.insert(parameter.to_string(), Value::Object(operators.clone()));
+    let mut metadata = Map::new();
+    if let Some(value) = value {
+        metadata.insert(parameter.to_string(), value.clone());
+    }
+    resolve_metadata_policy(&policy, &metadata)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// A value from one_of, the default when it is one of them
+fn one_of_choice(one_of: &[Value], operators: &Map<String, Value>) -> Fix {
+    match operators.get("default") {
+        Some(default) if one_of.contains(default) => Fix::SetValue(default.clone()),
+        _ => match one_of.first() {
+            Some(first) => Fix::SetValue(first.clone()),
+            None => Fix::Unfixable("one_of is empty".to_string()),
+        },
+    }
+}
+
+fn array<'a>(operators: &'a Map<String, Value>, operator: &str) -> Option<&'a Vec<Value>> {
+    operators.get(operator).and_then(Value::as_array)
+}
+
+fn fix_present(operators: &Map<String, Value>, value: &Value) -> Fix {
+    if let Some(one_of) = array(operators, "one_of") {
+        return one_of_choice(one_of, operators);
+    }
+    let Some(superset_of) = array(operators, "superset_of") else {
+        return Fix::Unfixable("no operator to satisfy".to_string());
+    };
+    // The list the superset_of check sees, after add and subset_of
+    let mut current: Vec<Value> = match value {
+        Value::Array(values) => values.clone(),
+        other => vec![other.clone()],
+    };
+    for added in array(operators, "add").into_iter().flatten() {
+        if !current.contains(added) {
+            current.push(added.clone());
+        }
+    }
+    let subset_of = array(operators, "subset_of");
+    if let Some(subset_of) = subset_of {
+        current.retain(|v| subset_of.contains(v));
+    }
+    let missing: Vec<Value> = superset_of
+        .iter()
+        .filter(|v| !current.contains(v))
+        .cloned()
+        .collect();
+    if let Some(subset_of) = subset_of {
+        let forbidden: Vec<&Value> = missing.iter().filter(|v| !subset_of.contains(v)).collect();
+        if !forbidden.is_empty() {
+            return Fix::Unfixable(format!(
+                "superset_of needs {} which subset_of does not allow",
+                json!(forbidden)
+            ));
+        }
+    }
+    Fix::AddValues(missing)
+}
+
+fn fix_missing(operators: &Map<String, Value>) -> Fix {
+    if let Some(one_of) = array(operators, "one_of") {
+        return one_of_choice(one_of, operators);
+    }
+    if let Some(superset_of) = array(operators, "superset_of") {
+        return Fix::SetValue(json!(superset_of));
+    }
+    if let Some(subset_of) = array(operators, "subset_of") {
+        return match subset_of.first() {
+            Some(first) => Fix::SetValue(json!([first])),
+            None => Fix::Unfixable("subset_of is empty".to_string()),
+        };
+    }
+    Fix::SupplyParameter
+}
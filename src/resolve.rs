@@ -0,0 +1,72 @@
+// Resolve responses, OpenID Federation section 8.3.2: what a resolver answers for an
+// entity, from a validated trust chain.
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+
+use crate::chain::ValidatedChain;
+use crate::jws::JwsSigner;
+
+pub const RESOLVE_RESPONSE_TYPE: &str = "resolve-response+jwt";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveResponse {
+    // The resolver
+    pub iss: String,
+    // The resolved entity
+    pub sub: String,
+    pub iat: i64,
+    // The earliest exp of the trust chain
+    pub exp: i64,
+    pub metadata: Map<String, Value>,
+    pub trust_marks: Option<Vec<Value>>,
+    // The signed statements of the chain, the leaf first
+    pub trust_chain: Vec<String>,
+}
+
+impl ResolveResponse {
+    // The response of the resolver at the time now, for the leaf of the chain.
+    pub fn from_chain(resolver: &str, chain: &ValidatedChain, now: i64) -> Result<ResolveResponse> {
+        if chain.expires_at <= now {
+            bail!("Resolve error: the trust chain has expired");
+        }
+        let leaf = chain.leaf();
+        Ok(ResolveResponse {
+            iss: resolver.to_string(),
+            sub: leaf.sub.clone(),
+            iat: now,
+            exp: chain.expires_at,
+            metadata: chain.metadata.clone(),
+            // Only the valid ones, and none when they were never checked
+            trust_marks: chain.trust_marks.as_ref().map(|_| {
+                chain
+                    .valid_trust_marks()
+                    .iter()
+                    .map(|t| t.to_claim())
+                    .collect()
+            }),
+            trust_chain: chain.trust_chain.clone(),
+        })
+    }
+
+    pub fn to_claims(&self) -> Value {
+        let mut claims = Map::new();
+        claims.insert("iss".to_string(), Value::from(self.iss.clone()));
+        claims.insert("sub".to_string(), Value::from(self.sub.clone()));
+        claims.insert("iat".to_string(), Value::from(self.iat));
+        claims.insert("exp".to_string(), Value::from(self.exp));
+        claims.insert("metadata".to_string(), Value::Object(self.metadata.clone()));
+        if let Some(trust_marks) = &self.trust_marks {
+            claims.insert("trust_marks".to_string(), Value::from(trust_marks.clone()));
+        }
+        claims.insert(
+            "trust_chain".to_string(),
+            Value::from(self.trust_chain.clone()),
+        );
+        Value::Object(claims)
+    }
+
+    // The response as a signed resolve-response+jwt.
+    pub fn sign(&self, signer: &JwsSigner) -> Result<String> {
+        signer.sign(RESOLVE_RESPONSE_TYPE, &self.to_claims())
+    }
+}
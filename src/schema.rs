@@ -0,0 +1,62 @@
+// JSON Schema (draft 2020-12) for the metadata_policy and metadata_policy_crit claims,
+// generated from the operator definitions in operators. The crate ships the output as
+// schema/metadata-policy.schema.json.
+use serde_json::{Map, Value, json};
+
+use crate::operators::{OPERATORS, Operand};
+
+fn operand_schema(operand: Operand) -> Value {
+    match operand {
+        Operand::Any => json!({}),
+        Operand::NonNull => json!({ "not": { "type": "null" } }),
+        Operand::Array => json!({ "type": "array" }),
+        Operand::Boolean => json!({ "type": "boolean" }),
+    }
+}
+
+// Schema of a document with the metadata_policy and metadata_policy_crit claims, such as
+// a subordinate statement. The policy of one entity type is #/$defs/entity_type_policy.
+pub fn metadata_policy_schema() -> Value {
+    let mut operators = Map::new();
+    for operator in OPERATORS.iter() {
+        let mut schema = operand_schema(operator.operand);
+        schema["description"] = json!(operator.description);
+        operators.insert(operator.name.to_string(), schema);
+    }
+    let standard: Vec<&str> = OPERATORS.iter().map(|o| o.name).collect();
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "OpenID Federation metadata_policy",
+        "type": "object",
+        "properties": {
+            "metadata_policy": { "$ref": "#/$defs/metadata_policy" },
+            "metadata_policy_crit": { "$ref": "#/$defs/metadata_policy_crit" },
+        },
+        "$defs": {
+            "metadata_policy": {
+                "description": "The policy for each entity type",
+                "type": "object",
+                "additionalProperties": { "$ref": "#/$defs/entity_type_policy" },
+            },
+            "entity_type_policy": {
+                "description": "The operators for each metadata parameter",
+                "type": "object",
+                "additionalProperties": { "$ref": "#/$defs/parameter_policy" },
+            },
+            "parameter_policy": {
+                "description": "Operators other than the standard ones must be understood when listed in metadata_policy_crit",
+                "type": "object",
+                "properties": operators,
+            },
+            "metadata_policy_crit": {
+                "description": "Operators other than the standard ones which must be understood",
+                "type": "array",
+                "items": {
+                    "type": "string",
+                    "not": { "enum": standard },
+                },
+                "uniqueItems": true,
+            },
+        },
+    })
+}
@@ -0,0 +1,428 @@
+// Entity Statements (OpenID Federation section 3) with their claims checked, and the
+// hand-off of their metadata_policy claims to merge_metadata_policies.
+//
+// Only the decoded claims are handled here, not the signed JWT.
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value};
+
+use std::fmt;
+use std::ops::Deref;
+
+use crate::merge_metadata_policies;
+use crate::operators::find_operator;
+
+// The claims defined by OpenID Federation, these can not be listed in crit
+const STANDARD_CLAIMS: [&str; 16] = [
+    "iss",
+    "sub",
+    "iat",
+    "exp",
+    "jwks",
+    "aud",
+    "authority_hints",
+    "metadata",
+    "metadata_policy",
+    "metadata_policy_crit",
+    "constraints",
+    "crit",
+    "trust_marks",
+    "trust_mark_issuers",
+    "trust_mark_owners",
+    "source_endpoint",
+];
+
+// The claims with a field in EntityStatement, the others go to other
+const TYPED_CLAIMS: [&str; 15] = [
+    "iss",
+    "sub",
+    "iat",
+    "exp",
+    "jwks",
+    "authority_hints",
+    "metadata",
+    "metadata_policy",
+    "metadata_policy_crit",
+    "constraints",
+    "crit",
+    "trust_marks",
+    "trust_mark_issuers",
+    "trust_mark_owners",
+    "source_endpoint",
+];
+
+// Claims only a superior can make about a subordinate
+const SUBORDINATE_ONLY: [&str; 4] = [
+    "metadata_policy",
+    "metadata_policy_crit",
+    "constraints",
+    "source_endpoint",
+];
+
+// Claims only an entity can make about itself
+const CONFIGURATION_ONLY: [&str; 4] = [
+    "authority_hints",
+    "trust_marks",
+    "trust_mark_issuers",
+    "trust_mark_owners",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityStatement {
+    pub iss: String,
+    pub sub: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jwks: Value,
+    pub authority_hints: Option<Vec<String>>,
+    pub metadata: Option<Map<String, Value>>,
+    pub metadata_policy: Option<Map<String, Value>>,
+    pub metadata_policy_crit: Option<Vec<String>>,
+    pub constraints: Option<Map<String, Value>>,
+    pub crit: Option<Vec<String>>,
+    pub trust_marks: Option<Vec<Value>>,
+    pub trust_mark_issuers: Option<Map<String, Value>>,
+    pub trust_mark_owners: Option<Map<String, Value>>,
+    pub source_endpoint: Option<String>,
+    // Every other claim, as it was
+    pub other: Map<String, Value>,
+}
+
+impl EntityStatement {
+    // Reads and checks the claims of an Entity Configuration (iss is sub) or of a
+    // Subordinate Statement.
+    pub fn from_claims(claims: &Value) -> Result<EntityStatement> {
+        let Some(claims) = claims.as_object() else {
+            bail!("Entity statement error: the claims must be a JSON object");
+        };
+        let statement = EntityStatement {
+            iss: required(claims, "iss", string)?,
+            sub: required(claims, "sub", string)?,
+            iat: required(claims, "iat", number)?,
+            exp: required(claims, "exp", number)?,
+            jwks: required(claims, "jwks", jwks)?,
+            authority_hints: optional(claims, "authority_hints", non_empty_strings)?,
+            metadata: optional(claims, "metadata", object)?,
+            metadata_policy: optional(claims, "metadata_policy", object)?,
+            metadata_policy_crit: optional(claims, "metadata_policy_crit", strings)?,
+            constraints: optional(claims, "constraints", object)?,
+            crit: optional(claims, "crit", strings)?,
+            trust_marks: optional(claims, "trust_marks", array)?,
+            trust_mark_issuers: optional(claims, "trust_mark_issuers", object)?,
+            trust_mark_owners: optional(claims, "trust_mark_owners", object)?,
+            source_endpoint: optional(claims, "source_endpoint", string)?,
+            other: claims
+                .iter()
+                .filter(|(name, _)| !TYPED_CLAIMS.contains(&name.as_str()))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        };
+
+        if statement.exp <= statement.iat {
+            bail!("Entity statement error: exp must be after iat");
+        }
+        let not_allowed: &[&str] = if statement.is_configuration() {
+            &SUBORDINATE_ONLY
+        } else {
+            &CONFIGURATION_ONLY
+        };
+        for name in not_allowed.iter() {
+            if claims.contains_key(*name) {
+                bail!(
+                    "Entity statement error: {} is not allowed in {}",
+                    name,
+                    statement.kind()
+                );
+            }
+        }
+        for name in statement.crit.iter().flatten() {
+            if STANDARD_CLAIMS.contains(&name.as_str()) {
+                bail!("Entity statement error: crit can not list {}", name);
+            }
+            if !claims.contains_key(name) {
+                bail!(
+                    "Entity statement error: {} is in crit but not present",
+                    name
+                );
+            }
+        }
+        for name in statement.metadata_policy_crit.iter().flatten() {
+            if find_operator(name).is_some() {
+                bail!(
+                    "Entity statement error: metadata_policy_crit can not list {}",
+                    name
+                );
+            }
+        }
+        Ok(statement)
+    }
+
+    // An Entity Configuration is issued by the entity about itself
+    pub fn is_configuration(&self) -> bool {
+        self.iss == self.sub
+    }
+
+    fn kind(&self) -> &'static str {
+        if self.is_configuration() {
+            "an entity configuration"
+        } else {
+            "a subordinate statement"
+        }
+    }
+
+    // The metadata_policy claim as merge_metadata_policies takes it, an empty policy when
+    // there is none.
+    pub fn policy(&self) -> Value {
+        Value::Object(self.metadata_policy.clone().unwrap_or_default())
+    }
+
+    // Fails when the policy uses an operator which metadata_policy_crit marks as
+    // critical, as this crate only understands the standard operators.
+    pub fn check_policy_crit(&self) -> Result<()> {
+        let (Some(policy), Some(crit)) = (&self.metadata_policy, &self.metadata_policy_crit) else {
+            return Ok(());
+        };
+        for (entity_type, entity_policy) in policy.iter() {
+            let parameters = entity_policy.as_object().into_iter().flatten();
+            for (parameter, operators) in parameters {
+                for operator in operators.as_object().into_iter().flat_map(|o| o.keys()) {
+                    if crit.contains(operator) {
+                        bail!(
+                            "Policy error: {}.{} uses the critical operator {} which is not supported",
+                            entity_type,
+                            parameter,
+                            operator
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The claims again, for signing or for passing on
+    pub fn to_claims(&self) -> Value {
+        let mut claims = self.other.clone();
+        claims.insert("iss".to_string(), Value::from(self.iss.clone()));
+        claims.insert("sub".to_string(), Value::from(self.sub.clone()));
+        claims.insert("iat".to_string(), Value::from(self.iat));
+        claims.insert("exp".to_string(), Value::from(self.exp));
+        claims.insert("jwks".to_string(), self.jwks.clone());
+        let mut insert = |name: &str, value: Option<Value>| {
+            if let Some(value) = value {
+                claims.insert(name.to_string(), value);
+            }
+        };
+        insert(
+            "authority_hints",
+            self.authority_hints.clone().map(Value::from),
+        );
+        insert("metadata", self.metadata.clone().map(Value::Object));
+        insert(
+            "metadata_policy",
+            self.metadata_policy.clone().map(Value::Object),
+        );
+        insert(
+            "metadata_policy_crit",
+            self.metadata_policy_crit.clone().map(Value::from),
+        );
+        insert("constraints", self.constraints.clone().map(Value::Object));
+        insert("crit", self.crit.clone().map(Value::from));
+        insert("trust_marks", self.trust_marks.clone().map(Value::Array));
+        insert(
+            "trust_mark_issuers",
+            self.trust_mark_issuers.clone().map(Value::Object),
+        );
+        insert(
+            "trust_mark_owners",
+            self.trust_mark_owners.clone().map(Value::Object),
+        );
+        insert(
+            "source_endpoint",
+            self.source_endpoint.clone().map(Value::from),
+        );
+        Value::Object(claims)
+    }
+}
+
+// An Entity Statement which an entity issued about itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityConfiguration(EntityStatement);
+
+impl EntityConfiguration {
+    pub fn from_claims(claims: &Value) -> Result<EntityConfiguration> {
+        EntityConfiguration::try_from(EntityStatement::from_claims(claims)?)
+    }
+
+    pub fn into_statement(self) -> EntityStatement {
+        self.0
+    }
+}
+
+impl TryFrom<EntityStatement> for EntityConfiguration {
+    type Error = anyhow::Error;
+
+    fn try_from(statement: EntityStatement) -> Result<EntityConfiguration> {
+        if !statement.is_configuration() {
+            bail!(
+                "Entity statement error: {} issued a statement about {}, not about itself",
+                statement.iss,
+                statement.sub
+            );
+        }
+        Ok(EntityConfiguration(statement))
+    }
+}
+
+impl Deref for EntityConfiguration {
+    type Target = EntityStatement;
+
+    fn deref(&self) -> &EntityStatement {
+        &self.0
+    }
+}
+
+// Merges the metadata_policy claims of subordinate statements, from the one issued by
+// the trust anchor down to the one about the leaf.
+pub fn merge_statement_policies(statements: &[EntityStatement]) -> Result<Map<String, Value>> {
+    let mut merged = Map::new();
+    for statement in statements.iter() {
+        let context = || format!("Failed to merge the policy of {}", statement.iss);
+        statement.check_policy_crit().with_context(context)?;
+        merged = merge_metadata_policies(&Value::Object(merged), &statement.policy())
+            .with_context(context)?;
+    }
+    Ok(merged)
+}
+
+// A parameter of the leaf metadata replaced by the metadata claim of a superior
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataOverride {
+    // The superior which issued the statement about the leaf
+    pub issuer: String,
+    pub entity_type: String,
+    pub parameter: String,
+    // None when the leaf did not have the parameter
+    pub before: Option<Value>,
+    pub value: Value,
+}
+
+impl fmt::Display for MetadataOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.before {
+            Some(before) => write!(
+                f,
+                "{}.{}: {} -> {} (from {})",
+                self.entity_type, self.parameter, before, self.value, self.issuer
+            ),
+            None => write!(
+                f,
+                "{}.{}: {} (from {})",
+                self.entity_type, self.parameter, self.value, self.issuer
+            ),
+        }
+    }
+}
+
+// Applies the metadata claim of the statement about the leaf to the metadata of the leaf,
+// before any policy (OpenID Federation section 6.1.4.2). Every parameter it has replaces
+// the one of the leaf. The metadata claims of statements about intermediates describe
+// the intermediates, not the leaf.
+pub fn apply_superior_metadata(
+    leaf_metadata: &Map<String, Value>,
+    superior: &EntityStatement,
+) -> Result<(Map<String, Value>, Vec<MetadataOverride>)> {
+    let mut metadata = leaf_metadata.clone();
+    let mut overrides = Vec::new();
+    let Some(superior_metadata) = &superior.metadata else {
+        return Ok((metadata, overrides));
+    };
+    for (entity_type, parameters) in superior_metadata.iter() {
+        let Some(parameters) = parameters.as_object() else {
+            bail!(
+                "Entity statement error: the metadata of {} for {} must be a JSON object",
+                superior.iss,
+                entity_type
+            );
+        };
+        let target = metadata
+            .entry(entity_type.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        let Some(target) = target.as_object_mut() else {
+            bail!(
+                "Entity statement error: the metadata of {} for {} must be a JSON object",
+                superior.sub,
+                entity_type
+            );
+        };
+        for (parameter, value) in parameters.iter() {
+            let before = target.insert(parameter.clone(), value.clone());
+            if before.as_ref() != Some(value) {
+                overrides.push(MetadataOverride {
+                    issuer: superior.iss.clone(),
+                    entity_type: entity_type.clone(),
+                    parameter: parameter.clone(),
+                    before,
+                    value: value.clone(),
+                });
+            }
+        }
+    }
+    Ok((metadata, overrides))
+}
+
+fn required<T>(
+    claims: &Map<String, Value>,
+    name: &str,
+    read: fn(&Value) -> Option<T>,
+) -> Result<T> {
+    match optional(claims, name, read)? {
+        Some(value) => Ok(value),
+        None => bail!("Entity statement error: {} is missing", name),
+    }
+}
+
+fn optional<T>(
+    claims: &Map<String, Value>,
+    name: &str,
+    read: fn(&Value) -> Option<T>,
+) -> Result<Option<T>> {
+    let Some(value) = claims.get(name) else {
+        return Ok(None);
+    };
+    match read(value) {
+        Some(value) => Ok(Some(value)),
+        None => bail!("Entity statement error: {} has the wrong type", name),
+    }
+}
+
+fn string(value: &Value) -> Option<String> {
+    value.as_str().map(String::from)
+}
+
+fn number(value: &Value) -> Option<i64> {
+    value.as_i64().or_else(|| value.as_f64().map(|f| f as i64))
+}
+
+fn object(value: &Value) -> Option<Map<String, Value>> {
+    value.as_object().cloned()
+}
+
+fn array(value: &Value) -> Option<Vec<Value>> {
+    value.as_array().cloned()
+}
+
+fn strings(value: &Value) -> Option<Vec<String>> {
+    value.as_array()?.iter().map(string).collect()
+}
+
+fn non_empty_strings(value: &Value) -> Option<Vec<String>> {
+    strings(value).filter(|s| !s.is_empty())
+}
+
+// A JWK Set, an object with an array of keys
+fn jwks(value: &Value) -> Option<Value> {
+    value
+        .get("keys")
+        .is_some_and(Value::is_array)
+        .then(|| value.clone())
+}
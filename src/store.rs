@@ -0,0 +1,81 @@
+// Signed entity statements kept locally, for building trust chains offline.
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::jws::Jws;
+
+pub trait StatementStore {
+    // The signed statement issued by issuer about subject, for an entity configuration
+    // both are the entity.
+    fn statement(&self, issuer: &str, subject: &str) -> Result<Option<String>>;
+
+    fn entity_configuration(&self, entity_id: &str) -> Result<Option<String>> {
+        self.statement(entity_id, entity_id)
+    }
+}
+
+// The iss and sub of a signed statement, without verifying it
+pub fn statement_ids(token: &str) -> Result<(String, String)> {
+    let jws = Jws::parse(token)?;
+    let id = |name: &str| match jws.payload.get(name).and_then(Value::as_str) {
+        Some(id) => Ok(id.to_string()),
+        None => bail!("Entity statement error: {} is missing", name),
+    };
+    Ok((id("iss")?, id("sub")?))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    // (iss, sub) to the signed statement
+    statements: HashMap<(String, String), String>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    // Adds a signed statement, replacing the one with the same iss and sub.
+    pub fn insert(&mut self, token: &str) -> Result<()> {
+        let ids = statement_ids(token)?;
+        self.statements.insert(ids, token.trim().to_string());
+        Ok(())
+    }
+
+    // Reads every .jwt file in a directory, each with one signed statement.
+    pub fn from_directory(dir: &Path) -> Result<MemoryStore> {
+        let mut store = MemoryStore::new();
+        let entries =
+            std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("jwt") {
+                continue;
+            }
+            let token = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            store
+                .insert(&token)
+                .with_context(|| format!("Failed to load {}", path.display()))?;
+        }
+        Ok(store)
+    }
+
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+}
+
+impl StatementStore for MemoryStore {
+    fn statement(&self, issuer: &str, subject: &str) -> Result<Option<String>> {
+        let key = (issuer.to_string(), subject.to_string());
+        Ok(self.statements.get(&key).cloned())
+    }
+}
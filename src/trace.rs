@@ -0,0 +1,93 @@
+// Explains what resolve_metadata_policy did to the metadata of one entity type.
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+use std::fmt;
+
+use crate::resolve_and_record;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterChange {
+    pub parameter: String,
+    // None when the parameter was not there
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    // One line for each operator of the policy, empty for a plain diff
+    pub reasons: Vec<String>,
+}
+
+impl ParameterChange {
+    pub fn changed(&self) -> bool {
+        self.before != self.after
+    }
+}
+
+impl fmt::Display for ParameterChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.before, &self.after) {
+            (None, Some(after)) => write!(f, "+ {}: {}", self.parameter, after)?,
+            (Some(before), None) => write!(f, "- {}: {}", self.parameter, before)?,
+            (Some(before), Some(after)) if before != after => {
+                write!(f, "~ {}: {} -> {}", self.parameter, before, after)?
+            }
+            (Some(before), Some(_)) => write!(f, "  {}: {}", self.parameter, before)?,
+            (None, None) => write!(f, "  {}: not set", self.parameter)?,
+        }
+        for reason in self.reasons.iter() {
+            write!(f, "\n      {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+// The parameters which are different in the resolved metadata.
+pub fn diff_metadata(
+    before: &Map<String, Value>,
+    after: &Map<String, Value>,
+) -> Vec<ParameterChange> {
+    let mut changes = Vec::new();
+    for name in before
+        .keys()
+        .chain(after.keys().filter(|k| !before.contains_key(*k)))
+    {
+        let change = ParameterChange {
+            parameter: name.clone(),
+            before: before.get(name).cloned(),
+            after: after.get(name).cloned(),
+            reasons: Vec::new(),
+        };
+        if change.changed() {
+            changes.push(change);
+        }
+    }
+    changes
+}
+
+// Every parameter with a policy or a change, with what each operator did to it while
+// the policy was applied.
+pub fn trace_resolution(
+    policy: &Map<String, Value>,
+    metadata: &Map<String, Value>,
+) -> Result<Vec<ParameterChange>> {
+    let mut records = Vec::new();
+    let resolved = resolve_and_record(policy, metadata, &mut records)?;
+    let resolved = resolved.as_object().unwrap();
+    let mut changes = diff_metadata(metadata, resolved);
+    for name in policy.keys() {
+        let reasons = records
+            .iter()
+            .filter(|(parameter, _)| parameter == name)
+            .map(|(_, reason)| reason.clone())
+            .collect();
+        match changes.iter_mut().find(|c| c.parameter == *name) {
+            Some(change) => change.reasons = reasons,
+            None => changes.push(ParameterChange {
+                parameter: name.clone(),
+                before: metadata.get(name).cloned(),
+                after: resolved.get(name).cloned(),
+                reasons,
+            }),
+        }
+    }
+    Ok(changes)
+}
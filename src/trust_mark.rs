@@ -0,0 +1,329 @@
+// Trust marks, OpenID Federation section 7. A trust mark is checked against the entity
+// configuration of a trust anchor: trust_mark_issuers says who may issue each type, and
+// trust_mark_owners who must have delegated the issuing.
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::{Value, json};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::chain::{TrustAnchor, ValidatedChain, build_trust_chains};
+use crate::jws::Jws;
+use crate::statement::EntityStatement;
+use crate::store::StatementStore;
+
+pub const TRUST_MARK_TYPE: &str = "trust-mark+jwt";
+pub const TRUST_MARK_DELEGATION_TYPE: &str = "trust-mark-delegation+jwt";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrustMark {
+    pub trust_mark_type: String,
+    pub iss: String,
+    pub sub: String,
+    pub iat: i64,
+    pub exp: Option<i64>,
+    // The owner of the trust mark type, when it delegated the issuing to iss
+    pub delegated_by: Option<String>,
+    pub claims: Value,
+    // The signed trust mark
+    pub token: String,
+}
+
+impl TrustMark {
+    // As in the trust_marks claim of an entity configuration
+    pub fn to_claim(&self) -> Value {
+        json!({"trust_mark_type": self.trust_mark_type, "trust_mark": self.token})
+    }
+}
+
+// What became of one entry of the trust_marks claim of the leaf
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrustMarkOutcome {
+    // None when the entry has no trust_mark_type
+    pub trust_mark_type: Option<String>,
+    pub result: std::result::Result<TrustMark, String>,
+}
+
+// Verifies a trust mark about entity_id at the time now, signed with a key of
+// issuer_jwks, against the entity configuration of the trust anchor.
+pub fn verify_trust_mark(
+    token: &str,
+    entity_id: &str,
+    trust_anchor: &EntityStatement,
+    issuer_jwks: &Value,
+    now: i64,
+) -> Result<TrustMark> {
+    let jws = Jws::parse(token)?;
+    jws.check_typ(TRUST_MARK_TYPE)?;
+    let claims = TimedClaims::read(&jws.payload, "trust mark")?;
+    if claims.sub != entity_id {
+        bail!(
+            "Trust mark error: the trust mark is about {}, not {}",
+            claims.sub,
+            entity_id
+        );
+    }
+    claims.check_time(now, "trust mark")?;
+
+    check_issuer(trust_anchor, &claims.trust_mark_type, &claims.iss)?;
+    jws.verify(issuer_jwks).with_context(|| {
+        format!(
+            "Trust mark error: the trust mark of {} is not valid",
+            claims.iss
+        )
+    })?;
+
+    let owner = trust_anchor
+        .trust_mark_owners
+        .as_ref()
+        .and_then(|owners| owners.get(&claims.trust_mark_type));
+    let delegated_by = match owner {
+        None => None,
+        Some(owner) => {
+            let Some(delegation) = jws.payload.get("delegation").and_then(Value::as_str) else {
+                bail!(
+                    "Trust mark error: {} has an owner, but the trust mark has no delegation",
+                    claims.trust_mark_type
+                );
+            };
+            Some(verify_delegation(delegation, owner, &claims, now)?)
+        }
+    };
+    Ok(TrustMark {
+        trust_mark_type: claims.trust_mark_type,
+        iss: claims.iss,
+        sub: claims.sub,
+        iat: claims.iat,
+        exp: claims.exp,
+        delegated_by,
+        claims: jws.payload,
+        token: token.trim().to_string(),
+    })
+}
+
+// Fails unless trust_mark_issuers of the trust anchor allows the issuer for the type.
+fn check_issuer(trust_anchor: &EntityStatement, trust_mark_type: &str, issuer: &str) -> Result<()> {
+    let issuers = trust_anchor
+        .trust_mark_issuers
+        .as_ref()
+        .and_then(|issuers| issuers.get(trust_mark_type));
+    let Some(issuers) = issuers.and_then(Value::as_array) else {
+        bail!(
+            "Trust mark error: {} does not recognise {}",
+            trust_anchor.iss,
+            trust_mark_type
+        );
+    };
+    // An empty list allows any issuer
+    if !issuers.is_empty() && !issuers.iter().any(|i| i == issuer) {
+        bail!(
+            "Trust mark error: {} may not issue {}",
+            issuer,
+            trust_mark_type
+        );
+    }
+    Ok(())
+}
+
+// The owner from trust_mark_owners delegates the issuing of the type to the issuer of the
+// trust mark. Returns the owner.
+fn verify_delegation(
+    token: &str,
+    owner: &Value,
+    trust_mark: &TimedClaims,
+    now: i64,
+) -> Result<String> {
+    let (Some(owner_id), Some(owner_jwks)) = (owner["sub"].as_str(), owner.get("jwks")) else {
+        bail!(
+            "Trust mark error: the owner of {} needs a sub and jwks",
+            trust_mark.trust_mark_type
+        );
+    };
+    let jws = Jws::parse(token).context("Trust mark error: the delegation is not valid")?;
+    jws.check_typ(TRUST_MARK_DELEGATION_TYPE)?;
+    jws.verify(owner_jwks).with_context(|| {
+        format!(
+            "Trust mark error: the delegation of {} is not valid",
+            owner_id
+        )
+    })?;
+    let delegation = TimedClaims::read(&jws.payload, "delegation")?;
+    if delegation.iss != owner_id {
+        bail!(
+            "Trust mark error: the delegation is issued by {}, not by the owner {}",
+            delegation.iss,
+            owner_id
+        );
+    }
+    if delegation.sub != trust_mark.iss || delegation.trust_mark_type != trust_mark.trust_mark_type
+    {
+        bail!(
+            "Trust mark error: the delegation is for {} to issue {}",
+            delegation.sub,
+            delegation.trust_mark_type
+        );
+    }
+    delegation.check_time(now, "delegation")?;
+    Ok(owner_id.to_string())
+}
+
+// The claims a trust mark and a delegation have in common
+struct TimedClaims {
+    iss: String,
+    sub: String,
+    trust_mark_type: String,
+    iat: i64,
+    exp: Option<i64>,
+}
+
+impl TimedClaims {
+    fn read(payload: &Value, what: &str) -> Result<TimedClaims> {
+        let string = |name: &str| match payload.get(name).and_then(Value::as_str) {
+            Some(value) => Ok(value.to_string()),
+            None => bail!("Trust mark error: the {} has no {}", what, name),
+        };
+        let Some(iat) = payload.get("iat").and_then(Value::as_i64) else {
+            bail!("Trust mark error: the {} has no iat", what);
+        };
+        let exp = match payload.get("exp") {
+            None => None,
+            Some(exp) => match exp.as_i64() {
+                Some(exp) => Some(exp),
+                None => bail!("Trust mark error: exp of the {} has the wrong type", what),
+            },
+        };
+        Ok(TimedClaims {
+            iss: string("iss")?,
+            sub: string("sub")?,
+            trust_mark_type: string("trust_mark_type")?,
+            iat,
+            exp,
+        })
+    }
+
+    fn check_time(&self, now: i64, what: &str) -> Result<()> {
+        if self.iat > now {
+            bail!("Trust mark error: the {} is issued in the future", what);
+        }
+        if self.exp.is_some_and(|exp| exp <= now) {
+            bail!("Trust mark error: the {} has expired", what);
+        }
+        Ok(())
+    }
+}
+
+// Checks every trust mark of the leaf of a chain against the trust anchor of the chain.
+// The keys of an issuer come from a trust chain from the issuer to the same trust anchor,
+// built with the statements of the store.
+pub fn check_trust_marks(
+    chain: &ValidatedChain,
+    store: &dyn StatementStore,
+    now: i64,
+) -> Vec<TrustMarkOutcome> {
+    TrustMarkChecker::new(store, now).check(chain)
+}
+
+// Checks the trust marks of several chains at the time now. The trust chain of each
+// issuer to a trust anchor is only built and validated once, its keys are kept.
+pub struct TrustMarkChecker<'a> {
+    store: &'a dyn StatementStore,
+    now: i64,
+    // (issuer, trust anchor) to the keys of the issuer, or why there are none
+    issuer_keys: RefCell<HashMap<(String, String), std::result::Result<Value, String>>>,
+}
+
+impl<'a> TrustMarkChecker<'a> {
+    pub fn new(store: &'a dyn StatementStore, now: i64) -> TrustMarkChecker<'a> {
+        TrustMarkChecker {
+            store,
+            now,
+            issuer_keys: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn check(&self, chain: &ValidatedChain) -> Vec<TrustMarkOutcome> {
+        let leaf = chain.leaf();
+        let anchor = chain.trust_anchor();
+        let mut outcomes = Vec::new();
+        for entry in leaf.trust_marks.iter().flatten() {
+            let trust_mark_type = entry["trust_mark_type"].as_str().map(String::from);
+            let result = self.check_entry(entry, &leaf.sub, anchor);
+            outcomes.push(TrustMarkOutcome {
+                trust_mark_type,
+                result: result.map_err(|e| format!("{:#}", e)),
+            });
+        }
+        outcomes
+    }
+
+    fn check_entry(
+        &self,
+        entry: &Value,
+        entity_id: &str,
+        anchor: &EntityStatement,
+    ) -> Result<TrustMark> {
+        let (Some(trust_mark_type), Some(token)) = (
+            entry["trust_mark_type"].as_str(),
+            entry["trust_mark"].as_str(),
+        ) else {
+            bail!(
+                "Trust mark error: an entry of trust_marks needs a trust_mark_type and trust_mark"
+            );
+        };
+        let issuer = Jws::parse(token)?.payload["iss"]
+            .as_str()
+            .map(String::from)
+            .unwrap_or_default();
+        // No keys are looked up for an issuer the trust anchor does not allow
+        check_issuer(anchor, trust_mark_type, &issuer)?;
+        let jwks = self.issuer_keys(&issuer, anchor)?;
+        let trust_mark = verify_trust_mark(token, entity_id, anchor, &jwks, self.now)?;
+        if trust_mark.trust_mark_type != trust_mark_type {
+            bail!(
+                "Trust mark error: the entry is for {}, the trust mark for {}",
+                trust_mark_type,
+                trust_mark.trust_mark_type
+            );
+        }
+        Ok(trust_mark)
+    }
+
+    fn issuer_keys(&self, issuer: &str, anchor: &EntityStatement) -> Result<Value> {
+        // The keys of the trust anchor itself are known from the chain
+        if issuer == anchor.iss {
+            return Ok(anchor.jwks.clone());
+        }
+        let key = (issuer.to_string(), anchor.iss.clone());
+        if let Some(keys) = self.issuer_keys.borrow().get(&key) {
+            return keys.clone().map_err(|e| anyhow!(e));
+        }
+        let keys = find_issuer_keys(issuer, anchor, self.store, self.now);
+        let keys = keys.map_err(|e| format!("{:#}", e));
+        self.issuer_keys.borrow_mut().insert(key, keys.clone());
+        keys.map_err(|e| anyhow!(e))
+    }
+}
+
+fn find_issuer_keys(
+    issuer: &str,
+    anchor: &EntityStatement,
+    store: &dyn StatementStore,
+    now: i64,
+) -> Result<Value> {
+    let anchors = [TrustAnchor {
+        entity_id: anchor.iss.clone(),
+        jwks: anchor.jwks.clone(),
+    }];
+    let search = build_trust_chains(issuer, &anchors, store)
+        .with_context(|| format!("Trust mark error: no trust chain for the issuer {}", issuer))?;
+    let (valid, problems) = search.validate(&anchors, now);
+    match valid.first() {
+        Some(chain) => Ok(chain.leaf().jwks.clone()),
+        None => bail!(
+            "Trust mark error: no valid trust chain from {} to {}: {}",
+            issuer,
+            anchor.iss,
+            problems.join("; ")
+        ),
+    }
+}
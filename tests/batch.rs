@@ -0,0 +1,119 @@
+use oidfed_metadata_policy::batch::{batch_resolve, document_files};
+use oidfed_metadata_policy::policy_file::PolicyFile;
+use serde_json::{Value, json};
+
+use std::path::PathBuf;
+
+// A new, empty directory
+fn directory(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("oidfed-batch-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn leaves(name: &str) -> PathBuf {
+    let dir = directory(name);
+    let files = [
+        ("a.json", r#"{"grant_types": ["authorization_code"]}"#),
+        (
+            "b.json",
+            r#"{"grant_types": ["implicit"], "scope": "email"}"#,
+        ),
+        ("c.json", "{not json"),
+        ("notes.txt", "not a leaf"),
+    ];
+    for (file, text) in files {
+        std::fs::write(dir.join(file), text).unwrap();
+    }
+    dir
+}
+
+fn policy() -> PolicyFile {
+    PolicyFile::Bare(json!({
+        "grant_types": {"subset_of": ["authorization_code"]},
+        "scope": {"one_of": ["openid"]},
+    }))
+}
+
+#[test]
+fn lists_the_documents_in_order() {
+    let dir = leaves("list");
+    let names: Vec<String> = document_files(&dir)
+        .unwrap()
+        .iter()
+        .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(names, ["a.json", "b.json", "c.json"]);
+}
+
+#[test]
+fn writes_the_leaves_which_resolve() {
+    let dir = leaves("write");
+    let output = dir.join("resolved");
+    let report = batch_resolve(&policy(), &dir, Some(&output)).unwrap();
+    assert_eq!(report.failed(), 2);
+    assert_eq!(
+        report.outcomes[0].result,
+        Ok(json!({"grant_types": ["authorization_code"]}))
+    );
+
+    let written: Value =
+        serde_json::from_str(&std::fs::read_to_string(output.join("a.resolved.json")).unwrap())
+            .unwrap();
+    assert_eq!(written, json!({"grant_types": ["authorization_code"]}));
+    assert!(!output.join("b.resolved.json").exists());
+    assert!(!output.join("c.resolved.json").exists());
+}
+
+#[test]
+fn reports_every_leaf_in_a_table() {
+    let dir = leaves("table");
+    let report = batch_resolve(&policy(), &dir, None).unwrap();
+    let lines: Vec<String> = report.to_string().lines().map(String::from).collect();
+    assert_eq!(lines[0], "LEAF    STATUS  REASON");
+    assert_eq!(lines[1], "a.json  ok");
+    assert_eq!(lines[2], "b.json  FAILED  Failed to find in one_of");
+    assert!(lines[3].starts_with("c.json  FAILED  Failed to parse"));
+    assert_eq!(lines[4], "");
+    assert_eq!(lines[5], "3 leaves: 1 resolved, 2 failed");
+}
+
+#[test]
+fn a_claim_policy_needs_documents_with_metadata() {
+    let dir = leaves("claim");
+    let policy = PolicyFile::Claim(json!({"openid_relying_party": {}}));
+    let report = batch_resolve(&policy, &dir, None).unwrap();
+    assert_eq!(report.failed(), 3);
+    assert_eq!(
+        report.outcomes[0].result,
+        Err("A metadata_policy claim needs a document with a metadata claim".to_string())
+    );
+}
+
+#[test]
+fn missing_leaves_directory() {
+    let dir = directory("missing").join("leaves");
+    let err = batch_resolve(&policy(), &dir, None).unwrap_err();
+    assert_eq!(err.to_string(), format!("Failed to read {}", dir.display()));
+}
+
+#[test]
+fn a_leaf_the_add_operator_can_not_apply_to_only_fails_itself() {
+    let dir = directory("add");
+    std::fs::write(dir.join("a.json"), r#"{"contacts": "ops@example.org"}"#).unwrap();
+    std::fs::write(dir.join("b.json"), r#"{"contacts": ["ops@example.org"]}"#).unwrap();
+    let policy = PolicyFile::Bare(json!({"contacts": {"add": ["help@example.org"]}}));
+    let report = batch_resolve(&policy, &dir, None).unwrap();
+    assert_eq!(report.failed(), 1);
+    assert_eq!(
+        report.outcomes[1].result,
+        Ok(json!({"contacts": ["ops@example.org", "help@example.org"]}))
+    );
+    let lines: Vec<String> = report.to_string().lines().map(String::from).collect();
+    assert_eq!(
+        lines[1],
+        "a.json  FAILED  Policy error: contacts: add needs an array in the metadata, not \"ops@example.org\""
+    );
+    assert_eq!(lines[4], "2 leaves: 1 resolved, 1 failed");
+}
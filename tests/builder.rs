@@ -0,0 +1,145 @@
+use oidfed_metadata_policy::builder::PolicyBuilder;
+use oidfed_metadata_policy::operators::OPERATORS;
+use oidfed_metadata_policy::{merge_metadata_policies, merge_policies};
+use serde_json::{Value, json};
+
+fn with_operator(builder: PolicyBuilder, operator: &str, operand: &Value) -> PolicyBuilder {
+    let values = || operand.as_array().unwrap().clone();
+    match operator {
+        "value" => builder.value(operand.clone()),
+        "add" => builder.add(values()),
+        "default" => builder.default(operand.clone()),
+        "one_of" => builder.one_of(values()),
+        "subset_of" => builder.subset_of(values()),
+        "superset_of" => builder.superset_of(values()),
+        "essential" => builder.essential(operand.as_bool().unwrap()),
+        _ => unreachable!(),
+    }
+}
+
+// Operands for each operator, some of them in conflict with each other
+fn operands(operator: &str) -> Vec<Value> {
+    match operator {
+        "value" => vec![json!(["a"]), Value::Null],
+        "essential" => vec![json!(true), json!(false)],
+        _ => vec![json!(["a"]), json!(["b"])],
+    }
+}
+
+#[test]
+fn builds_a_metadata_policy_claim() {
+    let policy = PolicyBuilder::new()
+        .entity_type("openid_relying_party")
+        .param("grant_types")
+        .subset_of(["authorization_code", "refresh_token"])
+        .superset_of(["authorization_code"])
+        .essential(true)
+        .param("token_endpoint_auth_method")
+        .one_of(["private_key_jwt"])
+        .entity_type("federation_entity")
+        .param("contacts")
+        .add(["ops@example.com"])
+        .build()
+        .unwrap();
+    assert_eq!(
+        policy,
+        json!({
+            "openid_relying_party": {
+                "grant_types": {
+                    "subset_of": ["authorization_code", "refresh_token"],
+                    "superset_of": ["authorization_code"],
+                    "essential": true,
+                },
+                "token_endpoint_auth_method": {"one_of": ["private_key_jwt"]},
+            },
+            "federation_entity": {"contacts": {"add": ["ops@example.com"]}},
+        })
+    );
+    assert!(merge_metadata_policies(&json!({}), &policy).is_ok());
+
+    let bare = PolicyBuilder::new()
+        .param("scope")
+        .value("openid")
+        .build()
+        .unwrap();
+    assert_eq!(bare, json!({"scope": {"value": "openid"}}));
+}
+
+#[test]
+fn rejects_invalid_combinations() {
+    let error = |builder: PolicyBuilder| builder.build().unwrap_err().to_string();
+    assert_eq!(
+        error(
+            PolicyBuilder::new()
+                .param("grant_types")
+                .add(["implicit"])
+                .subset_of(["authorization_code"])
+        ),
+        "Policy error: grant_types: the values of add must be a subset of the values of subset_of"
+    );
+    assert_eq!(
+        error(
+            PolicyBuilder::new()
+                .param("scope")
+                .value(serde_json::Value::Null)
+                .essential(true)
+        ),
+        "Policy error: scope: the value must be non-null when essential is true"
+    );
+    assert_eq!(
+        error(PolicyBuilder::new().essential(true)),
+        "Policy error: essential needs a parameter"
+    );
+    assert_eq!(
+        error(PolicyBuilder::new().param("contacts")),
+        "Policy error: contacts has no operators"
+    );
+}
+
+#[test]
+fn allows_the_combinations_merge_allows() {
+    let policy = PolicyBuilder::new()
+        .param("response_types")
+        .one_of(["code"])
+        .add(["code"])
+        .param("scope")
+        .one_of(["openid"])
+        .subset_of(["openid"])
+        .superset_of(["openid"])
+        .build()
+        .unwrap();
+    assert!(merge_policies(&policy, &json!({})).is_ok());
+}
+
+#[test]
+fn every_pair_of_operators_is_checked_like_merge() {
+    for (i, first) in OPERATORS.iter().enumerate() {
+        for second in OPERATORS[i + 1..].iter() {
+            for a in operands(first.name) {
+                for b in operands(second.name) {
+                    let built = with_operator(
+                        with_operator(PolicyBuilder::new().param("p"), first.name, &a),
+                        second.name,
+                        &b,
+                    )
+                    .build();
+                    let policy = json!({"p": {first.name: a, second.name: b}});
+                    let merged = merge_policies(&policy, &json!({}));
+                    let case = format!("{} {}", first.name, second.name);
+                    match (built, merged) {
+                        (Ok(built), Ok(_)) => assert_eq!(built, policy, "{}", case),
+                        (Err(built), Err(merged)) => assert_eq!(
+                            built.to_string(),
+                            merged
+                                .to_string()
+                                .replace("Subordinate policy merge error:", "Policy error: p:"),
+                            "{}",
+                            case
+                        ),
+                        (built, merged) => panic!("{}: {:?} but {:?}", case, built, merged),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,523 @@
+mod common;
+
+use common::{Entity, TestKey, sign_jwt};
+use oidfed_metadata_policy::chain::{
+    ChainSelection, TrustAnchor, build_trust_chains, validate_trust_chain,
+};
+use oidfed_metadata_policy::store::{MemoryStore, StatementStore};
+use serde_json::json;
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+const NOW: i64 = 1700000100;
+const TA: &str = "https://ta.example.org";
+const IA: &str = "https://ia.example.org";
+const RP: &str = "https://rp.example.org";
+
+fn entities() -> (Entity, Entity, Entity) {
+    (
+        Entity {
+            id: TA,
+            key: TestKey::ed(10),
+            alg: "EdDSA",
+        },
+        Entity {
+            id: IA,
+            key: TestKey::ec(11),
+            alg: "ES256",
+        },
+        Entity {
+            id: RP,
+            key: TestKey::ed(12),
+            alg: "EdDSA",
+        },
+    )
+}
+
+fn chain(ta: &Entity, ia: &Entity, rp: &Entity) -> Vec<String> {
+    vec![
+        rp.statement(
+            rp,
+            1700086400,
+            json!({
+                "authority_hints": [IA],
+                "metadata": {"openid_relying_party": {"grant_types": ["authorization_code", "implicit"]}},
+            }),
+        ),
+        ia.statement(
+            rp,
+            1700050000,
+            json!({"metadata_policy": {"openid_relying_party": {"grant_types": {"subset_of": ["authorization_code", "implicit"]}}}}),
+        ),
+        ta.statement(
+            ia,
+            1700086400,
+            json!({"metadata_policy": {"openid_relying_party": {"grant_types": {"subset_of": ["authorization_code", "refresh_token"]}}}}),
+        ),
+        ta.statement(ta, 1700090000, json!({})),
+    ]
+}
+
+#[test]
+fn validates_and_resolves_a_chain() {
+    let (ta, ia, rp) = entities();
+    let tokens = chain(&ta, &ia, &rp);
+    let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+    let anchor = TrustAnchor {
+        entity_id: TA.to_string(),
+        jwks: ta.jwks(),
+    };
+    let validated = validate_trust_chain(&tokens, &anchor, NOW).unwrap();
+    assert_eq!(
+        validated.metadata["openid_relying_party"]["grant_types"],
+        json!(["authorization_code"])
+    );
+    assert_eq!(validated.expires_at, 1700050000);
+    assert_eq!(validated.leaf().sub, RP);
+
+    let error = validate_trust_chain(&tokens, &anchor, 1700060000).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Trust chain error: statement 1 has expired"
+    );
+}
+
+#[test]
+fn applies_superior_metadata_before_policies() {
+    let (ta, ia, rp) = entities();
+    let tokens = [
+        rp.statement(
+            &rp,
+            1700086400,
+            json!({
+                "authority_hints": [IA],
+                "metadata": {"openid_relying_party": {"grant_types": ["implicit"], "client_name": "RP"}},
+            }),
+        ),
+        ia.statement(
+            &rp,
+            1700086400,
+            json!({"metadata": {"openid_relying_party": {"grant_types": ["authorization_code", "refresh_token"]}}}),
+        ),
+        ta.statement(
+            &ia,
+            1700086400,
+            json!({"metadata_policy": {"openid_relying_party": {"grant_types": {"subset_of": ["authorization_code"]}}}}),
+        ),
+        ta.statement(&ta, 1700086400, json!({})),
+    ];
+    let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+    let anchor = TrustAnchor {
+        entity_id: TA.to_string(),
+        jwks: ta.jwks(),
+    };
+    let validated = validate_trust_chain(&tokens, &anchor, NOW).unwrap();
+    assert_eq!(
+        validated.metadata["openid_relying_party"],
+        json!({"client_name": "RP", "grant_types": ["authorization_code"]})
+    );
+    let overrides: Vec<String> = validated.overrides.iter().map(|o| o.to_string()).collect();
+    assert_eq!(
+        overrides,
+        [
+            "openid_relying_party.grant_types: [\"implicit\"] -> [\"authorization_code\",\"refresh_token\"] (from https://ia.example.org)"
+        ]
+    );
+}
+
+#[test]
+fn rejects_broken_chains() {
+    let (ta, ia, rp) = entities();
+    let tokens = chain(&ta, &ia, &rp);
+    let anchor = TrustAnchor {
+        entity_id: TA.to_string(),
+        jwks: ta.jwks(),
+    };
+
+    // The trust anchor is known by other keys
+    let other = TrustAnchor {
+        entity_id: TA.to_string(),
+        jwks: json!({"keys": [TestKey::ed(13).jwk(TA)]}),
+    };
+    let all: Vec<&str> = tokens.iter().map(String::as_str).collect();
+    let error = validate_trust_chain(&all, &other, NOW).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Trust chain error: statement 3 is not valid"
+    );
+
+    // The statement about the intermediate is missing
+    let skipped = [tokens[0].as_str(), tokens[1].as_str(), tokens[3].as_str()];
+    let error = validate_trust_chain(&skipped, &anchor, NOW).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Trust chain error: statement 1 is not valid"
+    );
+
+    // Signed by the intermediate, but about another entity
+    let forged = ia.statement(&ia, 1700086400, json!({}));
+    let linked = [
+        tokens[0].as_str(),
+        forged.as_str(),
+        tokens[2].as_str(),
+        tokens[3].as_str(),
+    ];
+    let error = validate_trust_chain(&linked, &anchor, NOW).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Trust chain error: statement 0 is not valid"
+    );
+}
+
+#[test]
+fn builds_chains_from_a_store() {
+    let (ta, ia, rp) = entities();
+    let loop_a = Entity {
+        id: "https://a.example.org",
+        key: TestKey::ed(14),
+        alg: "EdDSA",
+    };
+    let loop_b = Entity {
+        id: "https://b.example.org",
+        key: TestKey::ed(15),
+        alg: "EdDSA",
+    };
+    let tokens = vec![
+        rp.statement(
+            &rp,
+            1700086400,
+            json!({
+                "authority_hints": [IA, loop_a.id, "https://gone.example.org"],
+                "metadata": {"openid_relying_party": {"grant_types": ["authorization_code"]}},
+            }),
+        ),
+        ia.statement(&rp, 1700086400, json!({})),
+        ia.statement(&ia, 1700086400, json!({"authority_hints": [TA]})),
+        ta.statement(&ia, 1700086400, json!({})),
+        ta.statement(&ta, 1700086400, json!({})),
+        loop_a.statement(&rp, 1700086400, json!({})),
+        loop_a.statement(&loop_a, 1700086400, json!({"authority_hints": [loop_b.id]})),
+        loop_b.statement(&loop_a, 1700086400, json!({})),
+        loop_b.statement(&loop_b, 1700086400, json!({"authority_hints": [loop_a.id]})),
+    ];
+    let dir = std::env::temp_dir().join(format!("oidfed-store-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (n, token) in tokens.iter().enumerate() {
+        std::fs::write(dir.join(format!("{}.jwt", n)), token).unwrap();
+    }
+    let store = MemoryStore::from_directory(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(store.len(), tokens.len());
+
+    let anchors = [TrustAnchor {
+        entity_id: TA.to_string(),
+        jwks: ta.jwks(),
+    }];
+    let search = build_trust_chains(RP, &anchors, &store).unwrap();
+    assert_eq!(
+        search.chains,
+        vec![vec![
+            tokens[0].clone(),
+            tokens[1].clone(),
+            tokens[3].clone(),
+            tokens[4].clone()
+        ]]
+    );
+    assert_eq!(
+        search.problems,
+        vec![
+            "loop: https://rp.example.org -> https://a.example.org -> https://b.example.org -> https://a.example.org",
+            "dead end at https://rp.example.org: no statement by https://gone.example.org about https://rp.example.org",
+        ]
+    );
+
+    let (valid, problems) = search.validate(&anchors, NOW);
+    assert_eq!(valid.len(), 1);
+    assert_eq!(problems.len(), 2);
+    assert_eq!(valid[0].trust_anchor().iss, TA);
+}
+
+#[test]
+fn enforces_constraints() {
+    let (ta, ia, rp) = entities();
+    let anchor = TrustAnchor {
+        entity_id: TA.to_string(),
+        jwks: ta.jwks(),
+    };
+    let validate = |constraints: serde_json::Value| {
+        let tokens = [
+            rp.statement(
+                &rp,
+                1700086400,
+                json!({
+                    "authority_hints": [IA],
+                    "metadata": {
+                        "federation_entity": {"organization_name": "RP"},
+                        "openid_provider": {"issuer": RP},
+                        "openid_relying_party": {"grant_types": ["authorization_code"]},
+                    },
+                }),
+            ),
+            ia.statement(&rp, 1700086400, json!({})),
+            ta.statement(&ia, 1700086400, json!({"constraints": constraints})),
+            ta.statement(&ta, 1700086400, json!({})),
+        ];
+        let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        validate_trust_chain(&tokens, &anchor, NOW).map_err(|e| format!("{:#}", e))
+    };
+
+    let validated = validate(json!({
+        "max_path_length": 1,
+        "naming_constraints": {"permitted": [".example.org"]},
+        "allowed_entity_types": ["openid_relying_party"],
+    }))
+    .unwrap();
+    let types: Vec<&String> = validated.metadata.keys().collect();
+    assert_eq!(types, ["federation_entity", "openid_relying_party"]);
+
+    assert_eq!(
+        validate(json!({"max_path_length": 0})).unwrap_err(),
+        "Trust chain error: the chain breaks a constraint: Constraints error: https://ta.example.org allows 0 intermediates below it, the chain has 1"
+    );
+    assert_eq!(
+        validate(json!({"naming_constraints": {"permitted": [".example.org"], "excluded": ["rp.example.org"]}})).unwrap_err(),
+        "Trust chain error: the chain breaks a constraint: Constraints error: the naming constraints of https://ta.example.org do not allow https://rp.example.org"
+    );
+    assert_eq!(
+        validate(json!({"naming_constraints": {"permitted": ["ta.example.org"]}})).unwrap_err(),
+        "Trust chain error: the chain breaks a constraint: Constraints error: the naming constraints of https://ta.example.org do not allow https://rp.example.org"
+    );
+}
+
+#[test]
+fn selects_among_trust_anchors() {
+    let (ta, ia, rp) = entities();
+    let other = Entity {
+        id: "https://other-ta.example.org",
+        key: TestKey::ec(16),
+        alg: "ES256",
+    };
+    let expired = Entity {
+        id: "https://expired-ta.example.org",
+        key: TestKey::ed(17),
+        alg: "EdDSA",
+    };
+    let policy = |scope: &str| json!({"metadata_policy": {"openid_relying_party": {"scope": {"value": scope}}}});
+    let mut store = MemoryStore::new();
+    for token in [
+        rp.statement(
+            &rp,
+            1700086400,
+            json!({
+                "authority_hints": [IA, other.id, expired.id],
+                "metadata": {"openid_relying_party": {"scope": "openid"}},
+            }),
+        ),
+        ia.statement(&rp, 1700080000, json!({})),
+        ia.statement(&ia, 1700086400, json!({"authority_hints": [TA]})),
+        ta.statement(&ia, 1700086400, policy("openid ta")),
+        ta.statement(&ta, 1700086400, json!({})),
+        other.statement(&rp, 1700086400, policy("openid other")),
+        other.statement(&other, 1700086400, json!({})),
+        expired.statement(&rp, 1700000050, json!({})),
+        expired.statement(&expired, 1700086400, json!({})),
+    ] {
+        store.insert(&token).unwrap();
+    }
+    let anchors: Vec<TrustAnchor> = [&ta, &other, &expired]
+        .iter()
+        .map(|e| TrustAnchor {
+            entity_id: e.id.to_string(),
+            jwks: e.jwks(),
+        })
+        .collect();
+    let search = build_trust_chains(RP, &anchors, &store).unwrap();
+
+    let resolution = search.resolve(&anchors, NOW, &ChainSelection::ShortestPath);
+    let outcomes: Vec<(&str, Vec<String>, bool)> = resolution
+        .outcomes
+        .iter()
+        .map(|o| (o.trust_anchor.as_str(), o.path.clone(), o.result.is_ok()))
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            (
+                TA,
+                vec![RP.to_string(), IA.to_string(), TA.to_string()],
+                true
+            ),
+            (other.id, vec![RP.to_string(), other.id.to_string()], true),
+            (
+                expired.id,
+                vec![RP.to_string(), expired.id.to_string()],
+                false
+            ),
+        ]
+    );
+    assert_eq!(
+        resolution.outcomes[2].result.as_ref().unwrap_err(),
+        "Trust chain error: statement 1 has expired"
+    );
+    let scope = |selection: ChainSelection| {
+        let resolution = search.resolve(&anchors, NOW, &selection);
+        resolution.selected().unwrap().metadata["openid_relying_party"]["scope"].clone()
+    };
+    assert_eq!(scope(ChainSelection::ShortestPath), "openid other");
+    assert_eq!(scope(ChainSelection::EarliestExpiry), "openid ta");
+    assert_eq!(
+        scope(ChainSelection::PreferredAnchors(vec![
+            expired.id.to_string(),
+            other.id.to_string()
+        ])),
+        "openid other"
+    );
+    assert_eq!(
+        scope(ChainSelection::PreferredAnchors(vec![TA.to_string()])),
+        "openid ta"
+    );
+}
+
+#[test]
+fn leaf_metadata_the_policy_can_not_apply_to_is_an_error() {
+    let (ta, ia, rp) = entities();
+    let tokens = [
+        rp.statement(
+            &rp,
+            1700086400,
+            json!({
+                "authority_hints": [IA],
+                "metadata": {"openid_relying_party": {"grant_types": "authorization_code"}},
+            }),
+        ),
+        ia.statement(
+            &rp,
+            1700050000,
+            json!({"metadata_policy": {"openid_relying_party": {"grant_types": {"add": ["refresh_token"]}}}}),
+        ),
+        ta.statement(&ia, 1700086400, json!({})),
+        ta.statement(&ta, 1700090000, json!({})),
+    ];
+    let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+    let anchor = TrustAnchor {
+        entity_id: TA.to_string(),
+        jwks: ta.jwks(),
+    };
+    let error = validate_trust_chain(&tokens, &anchor, NOW).unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        "Failed to resolve the openid_relying_party metadata: Policy error: grant_types: add needs an array in the metadata, not \"authorization_code\""
+    );
+}
+
+// A federation made up as it is asked about. Entity i of level n is
+// https://n-i.example.org, the leaf is https://0-0.example.org and every entity hints
+// all the entities of the next level. The trust anchor is the only entity of its level.
+struct MadeUpFederation {
+    key: TestKey,
+    width: usize,
+    // None for a federation without end
+    anchor_level: Option<usize>,
+    // (iss, sub) of every statement asked for
+    lookups: RefCell<Vec<(String, String)>>,
+}
+
+impl MadeUpFederation {
+    fn new(width: usize, anchor_level: Option<usize>) -> MadeUpFederation {
+        MadeUpFederation {
+            key: TestKey::ed(40),
+            width,
+            anchor_level,
+            lookups: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn level(entity_id: &str) -> usize {
+        let host = entity_id.trim_start_matches("https://");
+        host.split('-').next().unwrap().parse().unwrap()
+    }
+
+    fn anchor(&self) -> TrustAnchor {
+        let level = self.anchor_level.unwrap();
+        TrustAnchor {
+            entity_id: format!("https://{}-0.example.org", level),
+            jwks: json!({"keys": []}),
+        }
+    }
+
+    fn distinct_lookups(&self) -> usize {
+        let lookups = self.lookups.borrow();
+        lookups.iter().collect::<HashSet<_>>().len()
+    }
+}
+
+impl StatementStore for MadeUpFederation {
+    fn statement(&self, issuer: &str, subject: &str) -> anyhow::Result<Option<String>> {
+        self.lookups
+            .borrow_mut()
+            .push((issuer.to_string(), subject.to_string()));
+        let mut claims =
+            json!({"iss": issuer, "sub": subject, "iat": 1700000000, "exp": 1700086400});
+        let next = MadeUpFederation::level(issuer) + 1;
+        if issuer == subject && self.anchor_level != Some(next - 1) {
+            let width = if self.anchor_level == Some(next) {
+                1
+            } else {
+                self.width
+            };
+            let hints: Vec<String> = (0..width)
+                .map(|i| format!("https://{}-{}.example.org", next, i))
+                .collect();
+            claims["authority_hints"] = json!(hints);
+        }
+        let token = sign_jwt(&self.key, "EdDSA", issuer, "entity-statement+jwt", &claims);
+        Ok(Some(token))
+    }
+}
+
+#[test]
+fn paths_which_meet_look_up_statements_once() {
+    let federation = MadeUpFederation::new(2, Some(5));
+    let anchors = [federation.anchor()];
+    let search = build_trust_chains("https://0-0.example.org", &anchors, &federation).unwrap();
+    // Two ways up from each of the levels 0 to 3
+    assert_eq!(search.chains.len(), 16);
+    assert!(search.problems.is_empty());
+    // 10 entity configurations, 16 subordinate statements
+    assert_eq!(federation.lookups.borrow().len(), 26);
+    assert_eq!(federation.distinct_lookups(), 26);
+}
+
+#[test]
+fn endless_federations_end_the_search() {
+    // One long line of intermediates
+    let federation = MadeUpFederation::new(1, None);
+    let search = build_trust_chains("https://0-0.example.org", &[], &federation).unwrap();
+    assert!(search.chains.is_empty());
+    assert_eq!(search.problems.len(), 1);
+    assert!(search.problems[0].starts_with("dead end at https://0-0.example.org -> "));
+    assert!(
+        search.problems[0]
+            .ends_with(" -> https://9-0.example.org: the path is already 10 entities long")
+    );
+    assert_eq!(federation.lookups.borrow().len(), 19);
+
+    // New intermediates at every level
+    let federation = MadeUpFederation::new(20, None);
+    let error = build_trust_chains("https://0-0.example.org", &[], &federation).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Trust chain error: the search needs more than 100 statements"
+    );
+    assert_eq!(federation.lookups.borrow().len(), 100);
+
+    // Few intermediates, but ever more ways through them
+    let federation = MadeUpFederation::new(2, None);
+    let error = build_trust_chains("https://0-0.example.org", &[], &federation).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Trust chain error: the search follows more than 1000 authority_hints"
+    );
+    assert!(federation.lookups.borrow().len() < 100);
+}
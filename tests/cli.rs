@@ -0,0 +1,101 @@
+// Runs the command line tool itself.
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// A new directory with the files
+fn directory(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("oidfed-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, text) in files {
+        std::fs::write(dir.join(file), text).unwrap();
+    }
+    dir
+}
+
+fn run(args: &[&str], dir: &PathBuf) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_oidfed_metadata_policy"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+fn lines(bytes: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(bytes)
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn lint_reports_invalid_combinations_before_merging() {
+    let dir = directory(
+        "lint",
+        &[
+            (
+                "ta.json",
+                r#"{"grant_types": {"add": ["implicit"], "subset_of": ["authorization_code"]}}"#,
+            ),
+            (
+                "ia.json",
+                r#"{"jwks_uri": {"default": "https://ia.example.org/jwks"}}"#,
+            ),
+        ],
+    );
+
+    let output = run(&["lint", "ta.json"], &dir);
+    assert!(!output.status.success());
+    assert_eq!(
+        lines(&output.stdout),
+        [
+            "warning[invalid-combination] grant_types: the values of add must be a subset of the values of subset_of"
+        ]
+    );
+    assert_eq!(
+        lines(&output.stderr),
+        [
+            "Error: Invalid policy in ta.json: Subordinate policy merge error: the values of add must be a subset of the values of subset_of"
+        ]
+    );
+
+    // The findings of every file, then why they do not merge
+    let output = run(&["lint", "ta.json", "ia.json"], &dir);
+    assert!(!output.status.success());
+    assert_eq!(
+        lines(&output.stdout),
+        [
+            "warning[invalid-combination] grant_types: the values of add must be a subset of the values of subset_of",
+            "warning[default-for-entity-parameter] jwks_uri: default for a parameter which the entity itself should supply",
+        ]
+    );
+    assert_eq!(lines(&output.stderr).len(), 1);
+    assert!(lines(&output.stderr)[0].starts_with("Error: Invalid policy in ta.json"));
+}
+
+#[test]
+fn lint_reports_findings_of_the_files_and_of_the_merge_once() {
+    let dir = directory(
+        "lint-merge",
+        &[
+            (
+                "ta.json",
+                r#"{"jwks_uri": {"default": "https://ta.example.org/jwks"}, "scope": {"one_of": ["openid", "email"]}}"#,
+            ),
+            ("ia.json", r#"{"scope": {"add": ["openid"]}}"#),
+        ],
+    );
+    let output = run(&["lint", "--deny-warnings", "ta.json", "ia.json"], &dir);
+    assert!(!output.status.success());
+    assert_eq!(
+        lines(&output.stdout),
+        [
+            "warning[default-for-entity-parameter] jwks_uri: default for a parameter which the entity itself should supply",
+            "warning[add-with-one-of] scope: add makes a list of values, but one_of only allows a single value",
+        ]
+    );
+    assert!(output.stderr.is_empty());
+
+    let output = run(&["lint", "ta.json", "ia.json"], &dir);
+    assert!(output.status.success());
+}
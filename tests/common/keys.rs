@@ -0,0 +1,147 @@
+// Keys and signing for the tests of signed statements
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::signature::{RandomizedSigner, SignatureEncoding, Signer};
+use rsa::traits::{PrivateKeyParts, PublicKeyParts};
+use serde_json::{Value, json};
+use sha2::Sha256;
+
+pub enum TestKey {
+    Rsa(rsa::RsaPrivateKey),
+    Ec(p256::ecdsa::SigningKey),
+    Ed(ed25519_dalek::SigningKey),
+}
+
+fn b64(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+impl TestKey {
+    pub fn rsa() -> TestKey {
+        let pem = include_str!("../data/rsa-test-key.pem");
+        TestKey::Rsa(rsa::RsaPrivateKey::from_pkcs8_pem(pem).unwrap())
+    }
+
+    // Too small to be accepted
+    pub fn rsa_1024() -> TestKey {
+        let pem = include_str!("../data/rsa-1024-test-key.pem");
+        TestKey::Rsa(rsa::RsaPrivateKey::from_pkcs8_pem(pem).unwrap())
+    }
+
+    pub fn ec(seed: u8) -> TestKey {
+        TestKey::Ec(p256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap())
+    }
+
+    pub fn ed(seed: u8) -> TestKey {
+        TestKey::Ed(ed25519_dalek::SigningKey::from_bytes(&[seed; 32]))
+    }
+
+    pub fn jwk(&self, kid: &str) -> Value {
+        match self {
+            TestKey::Rsa(key) => json!({
+                "kty": "RSA",
+                "kid": kid,
+                "n": b64(&key.n().to_bytes_be()),
+                "e": b64(&key.e().to_bytes_be()),
+            }),
+            TestKey::Ec(key) => {
+                let point = key.verifying_key().to_encoded_point(false);
+                json!({
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "kid": kid,
+                    "x": b64(point.x().unwrap()),
+                    "y": b64(point.y().unwrap()),
+                })
+            }
+            TestKey::Ed(key) => json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "kid": kid,
+                "x": b64(key.verifying_key().as_bytes()),
+            }),
+        }
+    }
+
+    // The JWK with the private key
+    pub fn private_jwk(&self, kid: &str) -> Value {
+        let mut jwk = self.jwk(kid);
+        match self {
+            TestKey::Rsa(key) => {
+                jwk["d"] = json!(b64(&key.d().to_bytes_be()));
+                jwk["p"] = json!(b64(&key.primes()[0].to_bytes_be()));
+                jwk["q"] = json!(b64(&key.primes()[1].to_bytes_be()));
+            }
+            TestKey::Ec(key) => jwk["d"] = json!(b64(&key.to_bytes())),
+            TestKey::Ed(key) => jwk["d"] = json!(b64(key.as_bytes())),
+        }
+        jwk
+    }
+
+    pub fn sign(&self, alg: &str, message: &[u8]) -> Vec<u8> {
+        match (self, alg) {
+            (TestKey::Rsa(key), "RS256") => rsa::pkcs1v15::SigningKey::<Sha256>::new(key.clone())
+                .sign(message)
+                .to_vec(),
+            (TestKey::Rsa(key), "PS256") => rsa::pss::BlindedSigningKey::<Sha256>::new(key.clone())
+                .sign_with_rng(&mut rand_core::OsRng, message)
+                .to_vec(),
+            (TestKey::Ec(key), "ES256") => {
+                let signature: p256::ecdsa::Signature = key.sign(message);
+                signature.to_vec()
+            }
+            (TestKey::Ed(key), "EdDSA") => key.sign(message).to_vec(),
+            _ => panic!("can not sign {} with this key", alg),
+        }
+    }
+}
+
+pub fn sign_jwt(key: &TestKey, alg: &str, kid: &str, typ: &str, claims: &Value) -> String {
+    let header = json!({"alg": alg, "kid": kid, "typ": typ});
+    let input = format!(
+        "{}.{}",
+        b64(header.to_string().as_bytes()),
+        b64(claims.to_string().as_bytes())
+    );
+    let signature = key.sign(alg, input.as_bytes());
+    format!("{}.{}", input, b64(&signature))
+}
+
+// A federation entity signing with one key, the entity ID is the kid
+pub struct Entity {
+    pub id: &'static str,
+    pub key: TestKey,
+    pub alg: &'static str,
+}
+
+impl Entity {
+    pub fn jwks(&self) -> Value {
+        json!({"keys": [self.key.jwk(self.id)]})
+    }
+
+    pub fn sign(&self, claims: Value) -> String {
+        sign_jwt(
+            &self.key,
+            self.alg,
+            self.id,
+            "entity-statement+jwt",
+            &claims,
+        )
+    }
+
+    // A statement by this entity about the subject
+    pub fn statement(&self, subject: &Entity, exp: i64, extra: Value) -> String {
+        let mut claims = json!({
+            "iss": self.id,
+            "sub": subject.id,
+            "iat": 1700000000,
+            "exp": exp,
+            "jwks": subject.jwks(),
+        });
+        for (name, value) in extra.as_object().unwrap().iter() {
+            claims[name] = value.clone();
+        }
+        self.sign(claims)
+    }
+}
@@ -0,0 +1,16 @@
+// Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use serde_json::{Map, Value};
+
+#[cfg(feature = "jose")]
+mod keys;
+// Not every test uses the keys
+#[cfg(feature = "jose")]
+#[allow(unused_imports)]
+pub use keys::*;
+
+// The JSON object of a json! literal
+pub fn map(value: Value) -> Map<String, Value> {
+    value.as_object().unwrap().clone()
+}
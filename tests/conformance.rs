@@ -0,0 +1,165 @@
+use oidfed_metadata_policy::conformance::{
+    VectorFilter, VectorResult, json_report, junit_report, run_test_vectors, run_test_vectors_with,
+    summary,
+};
+use serde_json::{Value, json};
+
+use std::time::Duration;
+
+fn filter(text: &str) -> Result<VectorFilter, String> {
+    text.parse::<VectorFilter>().map_err(|e| format!("{:#}", e))
+}
+
+fn numbers(filter: &VectorFilter) -> Vec<i64> {
+    (0..=30).filter(|n| filter.matches(*n)).collect()
+}
+
+// A vector which passes, with the test number n
+fn vector(n: i64) -> Value {
+    json!({
+        "n": n,
+        "TA": {"scope": {"value": "openid"}},
+        "INT": {},
+        "merged": {"scope": {"value": "openid"}},
+        "metadata": {},
+        "resolved": {"scope": "openid"},
+    })
+}
+
+fn result(n: i64, failure: Option<&str>, millis: u64) -> VectorResult {
+    VectorResult {
+        n,
+        failure: failure.map(String::from),
+        duration: Duration::from_millis(millis),
+    }
+}
+
+#[test]
+fn filters_take_numbers_and_ranges() {
+    assert_eq!(numbers(&filter("7").unwrap()), vec![7]);
+    assert_eq!(numbers(&filter("10-13").unwrap()), vec![10, 11, 12, 13]);
+    assert_eq!(numbers(&filter("5-5").unwrap()), vec![5]);
+    assert_eq!(
+        numbers(&filter(" 1, 3 - 4 ,,20-21").unwrap()),
+        vec![1, 3, 4, 20, 21]
+    );
+    // Overlapping parts are fine
+    assert_eq!(numbers(&filter("2-4,3").unwrap()), vec![2, 3, 4]);
+}
+
+#[test]
+fn filters_reject_bad_input() {
+    let cases = [
+        ("", "No test numbers given"),
+        (" , ", "No test numbers given"),
+        ("a", "Invalid test number: a: invalid digit found in string"),
+        (
+            "1-b",
+            "Invalid end of test number range: 1-b: invalid digit found in string",
+        ),
+        (
+            "-5",
+            "Invalid start of test number range: -5: cannot parse integer from empty string",
+        ),
+        (
+            "1-2-3",
+            "Invalid end of test number range: 1-2-3: invalid digit found in string",
+        ),
+        ("20-10", "Empty test number range: 20-10"),
+        ("1,20-10", "Empty test number range: 20-10"),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(filter(text), Err(expected.to_string()), "{:?}", text);
+    }
+}
+
+#[test]
+fn runs_only_the_vectors_the_filter_accepts() {
+    let mut unnumbered = vector(0);
+    unnumbered.as_object_mut().unwrap().remove("n");
+    let vectors = [vector(10), vector(11), unnumbered, vector(12)];
+
+    let all = run_test_vectors(&vectors, None);
+    let run: Vec<i64> = all.iter().map(|r| r.n).collect();
+    // A vector without a number has its position in the file
+    assert_eq!(run, vec![10, 11, 3, 12]);
+    assert!(all.iter().all(VectorResult::passed));
+
+    let some = filter("3,11-20").unwrap();
+    let run: Vec<i64> = run_test_vectors(&vectors, Some(&some))
+        .iter()
+        .map(|r| r.n)
+        .collect();
+    assert_eq!(run, vec![11, 3, 12]);
+}
+
+#[test]
+fn a_panicking_vector_does_not_stop_the_run() {
+    let vectors = [vector(1), vector(2), vector(3), vector(4)];
+    let results = run_test_vectors_with(&vectors, None, |vector| match vector["n"].as_i64() {
+        Some(2) => panic!("broken vector"),
+        Some(3) => std::panic::panic_any(3),
+        Some(4) => Err("mismatch".to_string()),
+        _ => Ok(()),
+    });
+    let failures: Vec<(i64, Option<String>)> =
+        results.into_iter().map(|r| (r.n, r.failure)).collect();
+    assert_eq!(
+        failures,
+        vec![
+            (1, None),
+            (2, Some("Panicked: broken vector".to_string())),
+            (3, Some("Panicked: unknown cause".to_string())),
+            (4, Some("mismatch".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn summarizes_and_reports_as_json() {
+    let results = [
+        result(1, None, 2),
+        result(5, Some("Merged policy mismatch"), 500),
+    ];
+    assert_eq!(summary(&results), "2 vectors run: 1 passed, 1 failed");
+    assert_eq!(
+        json_report("vectors.json", &results),
+        json!({
+            "source": "vectors.json",
+            "total": 2,
+            "passed": 1,
+            "failed": 1,
+            "vectors": [
+                {"n": 1, "passed": true, "failure": null, "duration_ms": 2.0},
+                {"n": 5, "passed": false, "failure": "Merged policy mismatch", "duration_ms": 500.0},
+            ],
+        })
+    );
+    assert_eq!(summary(&[]), "0 vectors run: 0 passed, 0 failed");
+}
+
+#[test]
+fn junit_reports_escape_special_characters() {
+    let results = [
+        result(1, None, 250),
+        result(
+            2,
+            Some("expected {\"a\": [\"<b>\"]} & got 'c'\nsecond line"),
+            750,
+        ),
+    ];
+    let xml = junit_report("data/<A&B>.json", &results);
+    assert_eq!(
+        xml,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="2" failures="1" time="1.000000">
+  <testsuite name="data/&lt;A&amp;B&gt;.json" tests="2" failures="1" errors="0" time="1.000000">
+    <testcase classname="metadata_policy" name="vector 1" time="0.250000"/>
+    <testcase classname="metadata_policy" name="vector 2" time="0.750000">
+      <failure message="expected {&quot;a&quot;: [&quot;&lt;b&gt;&quot;]} &amp; got &apos;c&apos;&#10;second line"/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+    );
+}
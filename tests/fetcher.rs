@@ -0,0 +1,306 @@
+mod common;
+
+use common::{Entity, TestKey};
+use oidfed_metadata_policy::chain::TrustAnchor;
+use oidfed_metadata_policy::fetcher::{
+    FederationFetcher, MockFetcher, fetch_url, resolve_entity, well_known_url,
+};
+use serde_json::json;
+
+use std::collections::HashSet;
+
+const NOW: i64 = 1700000100;
+const EXP: i64 = 1700086400;
+
+fn federation_entity(entity: &Entity) -> serde_json::Value {
+    json!({"federation_entity": {"federation_fetch_endpoint": format!("{}/fetch", entity.id)}})
+}
+
+// A trust anchor with a leaf directly below it, the leaf has the extra claims
+fn small_federation(leaf_claims: serde_json::Value) -> (MockFetcher, Vec<TrustAnchor>) {
+    let ta = Entity {
+        id: "https://ta.example.org",
+        key: TestKey::ed(23),
+        alg: "EdDSA",
+    };
+    let rp = Entity {
+        id: "https://rp.example.org",
+        key: TestKey::ed(24),
+        alg: "EdDSA",
+    };
+    let mut federation = MockFetcher::new();
+    federation
+        .add_entity_configuration(&rp.statement(&rp, EXP, leaf_claims))
+        .unwrap();
+    federation
+        .add_entity_configuration(&ta.statement(
+            &ta,
+            EXP,
+            json!({"metadata": federation_entity(&ta)}),
+        ))
+        .unwrap();
+    federation
+        .add_subordinate_statement(
+            "https://ta.example.org/fetch",
+            &ta.statement(&rp, EXP, json!({})),
+        )
+        .unwrap();
+    let anchors = vec![TrustAnchor {
+        entity_id: ta.id.to_string(),
+        jwks: ta.jwks(),
+    }];
+    (federation, anchors)
+}
+
+// Fails every request for the URLs in failing, answers the others from the federation
+struct FailingFetcher {
+    federation: MockFetcher,
+    failing: HashSet<String>,
+}
+
+impl FailingFetcher {
+    fn check(&self, url: String) -> anyhow::Result<()> {
+        if self.failing.contains(&url) {
+            anyhow::bail!("Fetch error: GET {}: connection refused", url);
+        }
+        Ok(())
+    }
+}
+
+impl FederationFetcher for FailingFetcher {
+    fn entity_configuration(&self, entity_id: &str) -> anyhow::Result<Option<String>> {
+        self.check(well_known_url(entity_id)?)?;
+        self.federation.entity_configuration(entity_id)
+    }
+
+    fn fetch(&self, fetch_endpoint: &str, subject: &str) -> anyhow::Result<Option<String>> {
+        self.check(fetch_url(fetch_endpoint, subject)?)?;
+        self.federation.fetch(fetch_endpoint, subject)
+    }
+}
+
+#[test]
+fn resolves_through_a_mock_federation() {
+    let ta = Entity {
+        id: "https://ta.example.org",
+        key: TestKey::ed(20),
+        alg: "EdDSA",
+    };
+    let ia = Entity {
+        id: "https://ia.example.org",
+        key: TestKey::ec(21),
+        alg: "ES256",
+    };
+    let rp = Entity {
+        id: "https://rp.example.org",
+        key: TestKey::ed(22),
+        alg: "EdDSA",
+    };
+
+    let mut federation = MockFetcher::new();
+    federation
+        .add_entity_configuration(&rp.statement(
+            &rp,
+            EXP,
+            json!({
+                "authority_hints": [ia.id, "https://offline.example.org"],
+                "metadata": {"openid_relying_party": {"scope": "openid email"}},
+            }),
+        ))
+        .unwrap();
+    federation
+        .add_entity_configuration(&ia.statement(
+            &ia,
+            EXP,
+            json!({"authority_hints": [ta.id], "metadata": federation_entity(&ia)}),
+        ))
+        .unwrap();
+    federation
+        .add_entity_configuration(&ta.statement(
+            &ta,
+            EXP,
+            json!({"metadata": federation_entity(&ta)}),
+        ))
+        .unwrap();
+    federation
+        .add_subordinate_statement(
+            "https://ia.example.org/fetch",
+            &ia.statement(
+                &rp,
+                EXP,
+                json!({"metadata_policy": {"openid_relying_party": {"scope": {"value": "openid"}}}}),
+            ),
+        )
+        .unwrap();
+    federation
+        .add_subordinate_statement(
+            "https://ta.example.org/fetch",
+            &ta.statement(&ia, EXP, json!({})),
+        )
+        .unwrap();
+
+    let anchors = [TrustAnchor {
+        entity_id: ta.id.to_string(),
+        jwks: ta.jwks(),
+    }];
+    let (chains, problems) = resolve_entity(rp.id, &anchors, &federation, NOW).unwrap();
+    assert_eq!(chains.len(), 1);
+    assert_eq!(
+        chains[0].metadata["openid_relying_party"]["scope"],
+        "openid"
+    );
+    assert_eq!(
+        problems,
+        vec![
+            "dead end at https://rp.example.org: no statement by https://offline.example.org about https://rp.example.org"
+        ]
+    );
+    assert_eq!(
+        federation.requests(),
+        vec![
+            "https://rp.example.org/.well-known/openid-federation",
+            "https://ia.example.org/.well-known/openid-federation",
+            "https://ia.example.org/fetch?sub=https%3A%2F%2Frp.example.org",
+            "https://ta.example.org/.well-known/openid-federation",
+            "https://ta.example.org/fetch?sub=https%3A%2F%2Fia.example.org",
+            "https://offline.example.org/.well-known/openid-federation",
+        ]
+    );
+    assert_eq!(
+        fetch_url(
+            "https://ia.example.org/fetch?v=1",
+            "https://rp.example.org/a b"
+        )
+        .unwrap(),
+        "https://ia.example.org/fetch?v=1&sub=https%3A%2F%2Frp.example.org%2Fa%20b"
+    );
+}
+
+#[test]
+fn only_fetches_over_https() {
+    let error = well_known_url("http://rp.example.org").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Fetch error: http://rp.example.org is not an https URL"
+    );
+    let error = fetch_url("http://ta.example.org/fetch", "https://rp.example.org").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Fetch error: http://ta.example.org/fetch is not an https URL"
+    );
+
+    let (federation, anchors) =
+        small_federation(json!({"authority_hints": ["https://ta.example.org"]}));
+    let error = resolve_entity("http://rp.example.org", &anchors, &federation, NOW).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Fetch error: http://rp.example.org is not an https URL"
+    );
+    assert!(federation.requests().is_empty());
+
+    let rp = Entity {
+        id: "http://rp.example.org",
+        key: TestKey::ed(25),
+        alg: "EdDSA",
+    };
+    let mut federation = MockFetcher::new();
+    assert!(
+        federation
+            .add_entity_configuration(&rp.statement(&rp, EXP, json!({})))
+            .is_err()
+    );
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn http_fetcher_rejects_plain_http() {
+    use oidfed_metadata_policy::fetcher::HttpFetcher;
+
+    let fetcher = HttpFetcher::new();
+    let error = fetcher
+        .entity_configuration("http://127.0.0.1:9")
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Fetch error: http://127.0.0.1:9 is not an https URL"
+    );
+    let error = fetcher
+        .fetch("http://127.0.0.1:9/fetch", "https://rp.example.org")
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Fetch error: http://127.0.0.1:9/fetch is not an https URL"
+    );
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn http_fetcher_does_not_follow_redirects_to_plain_http() {
+    use oidfed_metadata_policy::fetcher::HttpFetcher;
+
+    // Every request of the agent, redirects included, must be over https
+    let fetcher = HttpFetcher::new();
+    assert!(fetcher.agent().config().https_only());
+    let error = fetcher
+        .agent()
+        .get("http://127.0.0.1:9/.well-known/openid-federation")
+        .call()
+        .unwrap_err();
+    assert!(
+        matches!(error, ureq::Error::RequireHttpsOnly(_)),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn a_failed_fetch_is_a_dead_end() {
+    let (federation, anchors) =
+        small_federation(json!({"authority_hints": ["https://ta.example.org"]}));
+    let fetcher = FailingFetcher {
+        federation,
+        failing: HashSet::from([
+            "https://ta.example.org/fetch?sub=https%3A%2F%2Frp.example.org".to_string(),
+        ]),
+    };
+    let (chains, problems) =
+        resolve_entity("https://rp.example.org", &anchors, &fetcher, NOW).unwrap();
+    assert!(chains.is_empty());
+    assert_eq!(
+        problems,
+        vec![
+            "dead end at https://rp.example.org: Fetch error: GET https://ta.example.org/fetch?sub=https%3A%2F%2Frp.example.org: connection refused"
+        ]
+    );
+
+    // Without the entity configuration of the leaf there is nothing to follow
+    let fetcher = FailingFetcher {
+        federation: fetcher.federation,
+        failing: HashSet::from(
+            ["https://rp.example.org/.well-known/openid-federation".to_string()],
+        ),
+    };
+    let error = resolve_entity("https://rp.example.org", &anchors, &fetcher, NOW).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Fetch error: GET https://rp.example.org/.well-known/openid-federation: connection refused"
+    );
+}
+
+#[test]
+fn a_leaf_without_authority_hints_has_no_chain() {
+    let (federation, anchors) = small_federation(json!({}));
+    let (chains, problems) =
+        resolve_entity("https://rp.example.org", &anchors, &federation, NOW).unwrap();
+    assert!(chains.is_empty());
+    assert_eq!(
+        problems,
+        vec![
+            "dead end at https://rp.example.org: https://rp.example.org has no authority_hints and is not a trust anchor"
+        ]
+    );
+    assert_eq!(
+        federation.requests(),
+        vec!["https://rp.example.org/.well-known/openid-federation"]
+    );
+}
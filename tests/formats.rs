@@ -0,0 +1,95 @@
+// Most of these need both the yaml and the toml feature, the others check what happens
+// without them.
+use oidfed_metadata_policy::formats::Format;
+#[cfg(all(feature = "yaml", feature = "toml"))]
+use oidfed_metadata_policy::formats::parse_document;
+#[cfg(all(feature = "yaml", feature = "toml"))]
+use serde_json::json;
+
+use std::path::Path;
+
+fn format_of(path: &str) -> Result<Format, String> {
+    Format::from_path(Path::new(path)).map_err(|e| e.to_string())
+}
+
+#[cfg(all(feature = "yaml", feature = "toml"))]
+#[test]
+fn format_comes_from_the_extension() {
+    assert_eq!(format_of("policy.yml"), Ok(Format::Yaml));
+    assert_eq!(format_of("policy.YAML"), Ok(Format::Yaml));
+    assert_eq!(format_of("policy.toml"), Ok(Format::Toml));
+    assert_eq!(format_of("policy.json"), Ok(Format::Json));
+    assert_eq!(format_of("policy"), Ok(Format::Json));
+}
+
+#[cfg(not(feature = "yaml"))]
+#[test]
+fn yaml_files_need_the_yaml_feature() {
+    for path in ["policy.yml", "policy.YAML"] {
+        assert_eq!(
+            format_of(path),
+            Err(format!(
+                "{} is a YAML file, but the yaml feature is not enabled",
+                path
+            ))
+        );
+    }
+    assert_eq!(format_of("policy.json"), Ok(Format::Json));
+}
+
+#[cfg(not(feature = "toml"))]
+#[test]
+fn toml_files_need_the_toml_feature() {
+    assert_eq!(
+        format_of("policy.toml"),
+        Err("policy.toml is a TOML file, but the toml feature is not enabled".to_string())
+    );
+    assert_eq!(format_of("policy"), Ok(Format::Json));
+}
+
+#[cfg(all(feature = "yaml", feature = "toml"))]
+#[test]
+fn yaml_and_toml_give_the_same_policy_as_json() {
+    let expected = json!({
+        "openid_relying_party": {
+            "grant_types": {"subset_of": ["authorization_code", "refresh_token"], "essential": true},
+            "default_max_age": {"value": 3600},
+        }
+    });
+    let yaml = r#"
+openid_relying_party:
+  grant_types:
+    subset_of: [authorization_code, refresh_token]
+    essential: true
+  default_max_age:
+    value: 3600
+"#;
+    let toml = r#"
+[openid_relying_party.grant_types]
+subset_of = ["authorization_code", "refresh_token"]
+essential = true
+
+[openid_relying_party.default_max_age]
+value = 3600
+"#;
+    assert_eq!(parse_document(yaml, Format::Yaml).unwrap(), expected);
+    assert_eq!(parse_document(toml, Format::Toml).unwrap(), expected);
+}
+
+#[cfg(all(feature = "yaml", feature = "toml"))]
+#[test]
+fn yaml_null_is_kept() {
+    let policy = parse_document("jwks:\n  value: null\n", Format::Yaml).unwrap();
+    assert_eq!(policy, json!({"jwks": {"value": null}}));
+}
+
+#[cfg(all(feature = "yaml", feature = "toml"))]
+#[test]
+fn errors_have_line_and_column() {
+    let yaml = parse_document("a:\n  b: [c\n", Format::Yaml).unwrap_err();
+    assert!(yaml.to_string().contains("line"), "{}", yaml);
+    let toml = parse_document("[a]\nb = \n", Format::Toml).unwrap_err();
+    assert!(toml.to_string().contains("line 2, column 5"), "{}", toml);
+    let json = parse_document("{\n  \"a\": }", Format::Json).unwrap_err();
+    assert!(json.to_string().contains("line 2 column"), "{}", json);
+}
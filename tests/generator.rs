@@ -0,0 +1,59 @@
+use oidfed_metadata_policy::conformance::check_test_vector;
+use oidfed_metadata_policy::generator::{
+    GeneratorConfig, default_parameters, generate_test_vectors, operator_combinations,
+};
+use oidfed_metadata_policy::{merge_policies, resolve_metadata_policy};
+use serde_json::{Value, json};
+
+#[test]
+fn generated_vectors_are_deterministic() {
+    let config = GeneratorConfig::default();
+    let first = generate_test_vectors(&config);
+    let second = generate_test_vectors(&config);
+    assert!(!first.is_empty());
+    assert_eq!(
+        serde_json::to_string(&first).unwrap(),
+        serde_json::to_string(&second).unwrap()
+    );
+    let numbers: Vec<i64> = first.iter().map(|v| v["n"].as_i64().unwrap()).collect();
+    assert_eq!(numbers, (1..=first.len() as i64).collect::<Vec<_>>());
+}
+
+#[test]
+fn expected_results_match_merge_and_resolve() {
+    let vectors = generate_test_vectors(&GeneratorConfig::default());
+    for vector in vectors.iter() {
+        check_test_vector(vector).unwrap_or_else(|e| panic!("{}: {:#}", vector["n"], e));
+        let merged = merge_policies(&vector["TA"], &vector["INT"]);
+        let Some(expected) = vector.get("merged") else {
+            assert_eq!(merged.unwrap_err().to_string(), vector["error"]);
+            continue;
+        };
+        let merged = merged.unwrap();
+        assert_eq!(&Value::Object(merged.clone()), expected);
+        let resolved = resolve_metadata_policy(&merged, vector["metadata"].as_object().unwrap());
+        match vector.get("resolved") {
+            Some(expected) => assert_eq!(&resolved.unwrap(), expected),
+            None => assert_eq!(resolved.unwrap_err().to_string(), vector["error"]),
+        }
+    }
+}
+
+#[test]
+fn respects_the_limits() {
+    let config = GeneratorConfig {
+        first_number: 100,
+        limit: Some(25),
+        ..GeneratorConfig::default()
+    };
+    let vectors = generate_test_vectors(&config);
+    assert_eq!(vectors.len(), 25);
+    assert_eq!(vectors[0]["n"], json!(100));
+
+    let operators = &default_parameters()[0].operators;
+    let combinations = operator_combinations(operators, 1);
+    assert!(combinations.iter().all(|c| c.len() <= 1));
+    // No operator, or one operator with one of its candidate values
+    let candidates: usize = operators.iter().map(|(_, values)| values.len()).sum();
+    assert_eq!(combinations.len(), 1 + candidates);
+}
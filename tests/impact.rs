@@ -0,0 +1,110 @@
+mod common;
+
+use common::map;
+use oidfed_metadata_policy::impact::{
+    Transition, metadata_policy_change_impact, policy_change_impact,
+};
+use serde_json::json;
+
+#[test]
+fn reports_each_kind_of_transition() {
+    let old = map(json!({"grant_types": {"subset_of": ["authorization_code", "implicit"]}}));
+    let new = map(json!({
+        "grant_types": {"subset_of": ["authorization_code"]},
+        "token_endpoint_auth_method": {"one_of": ["private_key_jwt"]},
+    }));
+    let subordinates = vec![
+        (
+            "unaffected".to_string(),
+            map(
+                json!({"grant_types": ["authorization_code"], "token_endpoint_auth_method": "private_key_jwt"}),
+            ),
+        ),
+        (
+            "changed".to_string(),
+            map(json!({"grant_types": ["authorization_code", "implicit"]})),
+        ),
+        (
+            "breaks".to_string(),
+            map(json!({"token_endpoint_auth_method": "client_secret_basic"})),
+        ),
+    ];
+    let impacts = policy_change_impact(&old, &new, &subordinates);
+    let transitions: Vec<Transition> = impacts.iter().map(|i| i.transition).collect();
+    assert_eq!(
+        transitions,
+        vec![
+            Transition::Unaffected,
+            Transition::Changed,
+            Transition::Breaks
+        ]
+    );
+
+    let changed = &impacts[1].changed_parameters;
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed[0].parameter, "grant_types");
+    assert_eq!(
+        changed[0].old,
+        Some(json!(["authorization_code", "implicit"]))
+    );
+    assert_eq!(changed[0].new, Some(json!(["authorization_code"])));
+
+    assert_eq!(impacts[2].new_errors, vec!["Failed to find in one_of"]);
+    assert_eq!(impacts[2].to_json()["transition"], "breaks");
+}
+
+#[test]
+fn metadata_policy_changes_are_per_entity_type() {
+    let old =
+        map(json!({"openid_relying_party": {"grant_types": {"superset_of": ["refresh_token"]}}}));
+    let new = map(
+        json!({"openid_relying_party": {"grant_types": {"subset_of": ["authorization_code", "refresh_token"]}}}),
+    );
+    let subordinates = vec![(
+        "https://rp.example.org".to_string(),
+        map(json!({
+            "openid_relying_party": {"grant_types": ["authorization_code"]},
+            "federation_entity": {"organization_name": "Example"},
+        })),
+    )];
+    let impacts = metadata_policy_change_impact(&old, &new, &subordinates);
+    assert_eq!(impacts[0].transition, Transition::Fixed);
+    assert!(impacts[0].new_errors.is_empty());
+
+    let reversed = metadata_policy_change_impact(&new, &old, &subordinates);
+    assert_eq!(reversed[0].transition, Transition::Breaks);
+    assert_eq!(
+        reversed[0].new_errors,
+        vec!["openid_relying_party: superset_of failed"]
+    );
+}
+
+#[test]
+fn an_invalid_new_policy_breaks_every_subordinate() {
+    let old = map(json!({"grant_types": {"subset_of": ["authorization_code"]}}));
+    let new = map(json!({"grant_types": {"subset_of": "authorization_code"}}));
+    let subordinates = vec![("rp".to_string(), map(json!({"grant_types": []})))];
+    let impacts = policy_change_impact(&old, &new, &subordinates);
+    assert_eq!(impacts[0].transition, Transition::Breaks);
+    assert_eq!(
+        impacts[0].new_errors,
+        vec!["Policy error: grant_types: subset_of must be an array, not \"authorization_code\""]
+    );
+
+    let impacts = policy_change_impact(&new, &new, &subordinates);
+    assert_eq!(impacts[0].transition, Transition::StillFails);
+    // The error was there under the old policy too
+    assert!(impacts[0].new_errors.is_empty());
+}
+
+#[test]
+fn entity_metadata_which_is_not_an_object_fails_under_both_policies() {
+    let policy = map(json!({"openid_relying_party": {}}));
+    let subordinates = vec![(
+        "rp".to_string(),
+        map(json!({"openid_relying_party": ["authorization_code"]})),
+    )];
+    let impacts = metadata_policy_change_impact(&policy, &policy, &subordinates);
+    assert_eq!(impacts[0].transition, Transition::StillFails);
+    assert_eq!(impacts[0].to_json()["transition"], "still_fails");
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use common::{TestKey, sign_jwt};
+use oidfed_metadata_policy::jws::{
+    Jws, JwsSigner, verify_entity_configuration, verify_entity_statement,
+};
+use serde_json::{Value, json};
+
+fn claims(iss: &str, sub: &str, jwks: Value) -> Value {
+    json!({
+        "iss": iss,
+        "sub": sub,
+        "iat": 1700000000,
+        "exp": 1700086400,
+        "jwks": jwks,
+    })
+}
+
+#[test]
+fn verifies_every_algorithm() {
+    let keys = [
+        (TestKey::rsa(), "RS256"),
+        (TestKey::rsa(), "PS256"),
+        (TestKey::ec(1), "ES256"),
+        (TestKey::ed(2), "EdDSA"),
+    ];
+    let jwks = json!({"keys": keys.iter().map(|(k, alg)| k.jwk(alg)).collect::<Vec<_>>()});
+    for (key, alg) in keys.iter() {
+        let claims = claims(
+            "https://ia.example.org",
+            "https://rp.example.org",
+            json!({"keys": []}),
+        );
+        let token = sign_jwt(key, alg, alg, "entity-statement+jwt", &claims);
+        let statement =
+            verify_entity_statement(&token, &jwks).unwrap_or_else(|e| panic!("{}: {:#}", alg, e));
+        assert_eq!(statement.sub, "https://rp.example.org");
+    }
+}
+
+#[test]
+fn signs_with_private_jwks() {
+    let keys = [
+        (TestKey::rsa(), "RS256"),
+        (TestKey::rsa(), "PS256"),
+        (TestKey::ec(1), "ES256"),
+        (TestKey::ed(2), "EdDSA"),
+    ];
+    for (key, alg) in keys.iter() {
+        let signer = JwsSigner::from_jwk(&key.private_jwk("signer"), alg).unwrap();
+        assert_eq!(signer.public_jwk(), key.jwk("signer"));
+        let claims = claims(
+            "https://ia.example.org",
+            "https://rp.example.org",
+            json!({"keys": []}),
+        );
+        let token = signer.sign("entity-statement+jwt", &claims).unwrap();
+        verify_entity_statement(&token, &json!({"keys": [signer.public_jwk()]}))
+            .unwrap_or_else(|e| panic!("{}: {:#}", alg, e));
+    }
+
+    let error = JwsSigner::from_jwk(&TestKey::ec(1).jwk("public"), "ES256").unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        "JWS error: key public can not sign ES256: the key has no d"
+    );
+}
+
+#[test]
+fn verifies_entity_configurations_with_their_own_keys() {
+    let key = TestKey::ed(3);
+    let own = json!({"keys": [key.jwk("own")]});
+    let claims = claims("https://rp.example.org", "https://rp.example.org", own);
+    let token = sign_jwt(&key, "EdDSA", "own", "entity-statement+jwt", &claims);
+    let configuration = verify_entity_configuration(&token).unwrap();
+    assert_eq!(configuration.iss, "https://rp.example.org");
+
+    let other = TestKey::ed(4);
+    let token = sign_jwt(&other, "EdDSA", "own", "entity-statement+jwt", &claims);
+    let error = verify_entity_configuration(&token).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "JWS error: the signature does not verify with key own"
+    );
+}
+
+#[test]
+fn rejects_wrong_headers() {
+    let key = TestKey::ec(5);
+    let jwks = json!({"keys": [key.jwk("k1")]});
+    let claims = claims(
+        "https://ia.example.org",
+        "https://rp.example.org",
+        json!({"keys": []}),
+    );
+
+    let token = sign_jwt(&key, "ES256", "k1", "JWT", &claims);
+    assert_eq!(
+        verify_entity_statement(&token, &jwks)
+            .unwrap_err()
+            .to_string(),
+        "JWS error: typ must be entity-statement+jwt, not \"JWT\""
+    );
+
+    let token = sign_jwt(
+        &key,
+        "ES256",
+        "k2",
+        "application/entity-statement+jwt",
+        &claims,
+    );
+    assert_eq!(
+        verify_entity_statement(&token, &jwks)
+            .unwrap_err()
+            .to_string(),
+        "JWS error: no key with kid k2"
+    );
+
+    let token = sign_jwt(&key, "ES256", "k1", "entity-statement+jwt", &claims);
+    let jws = Jws::parse(&token).unwrap();
+    assert_eq!(jws.alg(), Some("ES256"));
+    let tampered = token.replacen('.', ".e30", 1);
+    assert!(verify_entity_statement(&tampered, &jwks).is_err());
+    assert!(Jws::parse("not.a-jws").is_err());
+}
+
+#[test]
+fn rejects_rsa_keys_under_2048_bits() {
+    let key = TestKey::rsa_1024();
+    let error = JwsSigner::from_jwk(&key.private_jwk("small"), "RS256").unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        "JWS error: key small can not sign RS256: the RSA key has 1024 bits, at least 2048 are needed"
+    );
+
+    for alg in ["RS256", "PS256"] {
+        let claims = claims(
+            "https://ia.example.org",
+            "https://rp.example.org",
+            json!({"keys": []}),
+        );
+        let token = sign_jwt(&key, alg, "small", "entity-statement+jwt", &claims);
+        let error =
+            verify_entity_statement(&token, &json!({"keys": [key.jwk("small")]})).unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "JWS error: the signature does not verify with key small: the RSA key has 1024 bits, at least 2048 are needed"
+        );
+    }
+}
+
+#[test]
+fn blinded_rs256_signatures_are_the_plain_ones() {
+    // PKCS#1 v1.5 signatures are deterministic, blinding must not change them
+    let key = TestKey::rsa();
+    let signer = JwsSigner::from_jwk(&key.private_jwk("ia"), "RS256").unwrap();
+    let claims = claims(
+        "https://ia.example.org",
+        "https://rp.example.org",
+        json!({"keys": []}),
+    );
+    let token = signer.sign("entity-statement+jwt", &claims).unwrap();
+    assert_eq!(
+        token,
+        sign_jwt(&key, "RS256", "ia", "entity-statement+jwt", &claims)
+    );
+    assert_eq!(signer.sign("entity-statement+jwt", &claims).unwrap(), token);
+}
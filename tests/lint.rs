@@ -0,0 +1,85 @@
+use oidfed_metadata_policy::lint::{lint_metadata_policy, lint_policy};
+use serde_json::{Value, json};
+
+fn codes(policy: Value, entity_type: Option<&str>) -> Vec<&'static str> {
+    lint_policy(entity_type, policy.as_object().unwrap())
+        .into_iter()
+        .map(|f| f.code)
+        .collect()
+}
+
+#[test]
+fn clean_policy_has_no_findings() {
+    let policy = json!({
+        "grant_types": {"subset_of": ["authorization_code"], "essential": true},
+        "token_endpoint_auth_method": {"one_of": ["private_key_jwt"]},
+    });
+    assert!(codes(policy, Some("openid_relying_party")).is_empty());
+}
+
+#[test]
+fn finds_each_risky_construct() {
+    assert_eq!(
+        codes(
+            json!({"jwks_uri": {"default": "https://ta.example/jwks"}}),
+            None
+        ),
+        vec!["default-for-entity-parameter"]
+    );
+    assert_eq!(
+        codes(json!({"jwks": {"value": null}}), None),
+        vec!["null-value-security-parameter"]
+    );
+    assert_eq!(
+        codes(
+            json!({"scope": {"one_of": ["openid"], "add": ["openid"]}}),
+            None
+        ),
+        vec!["add-with-one-of"]
+    );
+    assert_eq!(
+        codes(
+            json!({"grant_types": {"value": ["authorization_code"], "default": ["implicit"], "essential": true}}),
+            None
+        ),
+        vec!["unreachable-after-value"]
+    );
+    let findings = lint_policy(
+        None,
+        json!({"grant_types": {"add": ["implicit"], "subset_of": ["authorization_code"]}})
+            .as_object()
+            .unwrap(),
+    );
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].code, "invalid-combination");
+    assert_eq!(
+        findings[0].message,
+        "the values of add must be a subset of the values of subset_of"
+    );
+}
+
+#[test]
+fn unknown_parameters_need_a_known_entity_type() {
+    let policy = json!({"not_a_parameter": {"essential": true}});
+    assert!(codes(policy.clone(), None).is_empty());
+    assert!(codes(policy.clone(), Some("example_entity_type")).is_empty());
+    assert_eq!(
+        codes(policy, Some("openid_provider")),
+        vec!["unknown-parameter"]
+    );
+}
+
+#[test]
+fn metadata_policy_findings_carry_the_entity_type() {
+    let policy = json!({
+        "openid_relying_party": {"jwks": {"value": null}},
+        "federation_entity": {"organization_name": {"value": "Example"}},
+    });
+    let findings = lint_metadata_policy(policy.as_object().unwrap());
+    assert_eq!(findings.len(), 1);
+    assert_eq!(
+        findings[0].entity_type.as_deref(),
+        Some("openid_relying_party")
+    );
+    assert_eq!(findings[0].to_json()["parameter"], "jwks");
+}
@@ -0,0 +1,219 @@
+// The merge and resolve behaviour which the test vectors depend on.
+mod common;
+
+use common::map;
+use oidfed_metadata_policy::{
+    merge_metadata_policies, merge_policies, resolve_metadata_policies, resolve_metadata_policy,
+};
+use serde_json::{Value, json};
+
+#[test]
+fn keeps_parameters_only_in_the_subordinate_policy() {
+    let merged = merge_policies(
+        &json!({"grant_types": {"subset_of": ["a", "b"]}}),
+        &json!({"contacts": {"add": ["ops@example.org"]}}),
+    )
+    .unwrap();
+    assert_eq!(
+        Value::Object(merged),
+        json!({
+            "contacts": {"add": ["ops@example.org"]},
+            "grant_types": {"subset_of": ["a", "b"]},
+        })
+    );
+}
+
+#[test]
+fn subordinate_only_parameters_apply_to_the_leaf() {
+    let merged = merge_metadata_policies(
+        &json!({"openid_relying_party": {"grant_types": {"subset_of": ["authorization_code"]}}}),
+        &json!({"openid_relying_party": {
+            "contacts": {"add": ["ops@ia.example.org"]},
+            "client_name": {"essential": true},
+        }}),
+    )
+    .unwrap();
+    let metadata = map(json!({"openid_relying_party": {
+        "grant_types": ["authorization_code"],
+        "contacts": ["rp@example.org"],
+        "client_name": "RP",
+    }}));
+    let resolved = resolve_metadata_policies(&merged, &metadata).unwrap();
+    assert_eq!(
+        resolved["openid_relying_party"]["contacts"],
+        json!(["rp@example.org", "ops@ia.example.org"])
+    );
+
+    let metadata = map(json!({"openid_relying_party": {"grant_types": ["authorization_code"]}}));
+    let error = resolve_metadata_policies(&merged, &metadata).unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        "Failed to resolve the openid_relying_party metadata: We have an essential policy but not metadata"
+    );
+}
+
+#[test]
+fn merged_subset_of_keeps_the_superior_order() {
+    for _ in 0..10 {
+        let merged = merge_policies(
+            &json!({"grant_types": {"subset_of": ["d", "c", "b", "a"]}}),
+            &json!({"grant_types": {"subset_of": ["a", "b", "c", "e"]}}),
+        )
+        .unwrap();
+        assert_eq!(merged["grant_types"]["subset_of"], json!(["c", "b", "a"]));
+    }
+}
+
+#[test]
+fn merged_subset_of_does_not_depend_on_the_subordinate_order() {
+    let superior = json!({"openid_relying_party": {"response_types": {"subset_of": ["code", "id_token", "code id_token"]}}});
+    let orders = [
+        json!(["code id_token", "id_token", "code", "token"]),
+        json!(["token", "code", "code id_token", "id_token"]),
+        json!(["id_token", "code", "code id_token"]),
+    ];
+    for order in orders {
+        let subordinate = json!({"openid_relying_party": {"response_types": {"subset_of": order}}});
+        let merged = merge_metadata_policies(&superior, &subordinate).unwrap();
+        assert_eq!(
+            Value::Object(merged).to_string(),
+            r#"{"openid_relying_party":{"response_types":{"subset_of":["code","id_token","code id_token"]}}}"#
+        );
+    }
+}
+
+#[test]
+fn essential_alone_keeps_the_metadata_value() {
+    let resolved = resolve_metadata_policy(
+        &map(json!({"client_name": {"essential": true}})),
+        &map(json!({"client_name": "RP"})),
+    )
+    .unwrap();
+    assert_eq!(resolved, json!({"client_name": "RP"}));
+}
+
+#[test]
+fn policies_which_do_not_change_the_value_keep_it() {
+    let metadata = map(json!({"client_name": "RP", "contacts": ["rp@example.org"]}));
+    let policies = [
+        json!({"client_name": {}}),
+        json!({"client_name": {"essential": false}}),
+        json!({"client_name": {"essential": true}, "contacts": {"essential": true}}),
+        json!({"client_name": {"regexp": "^R"}}),
+    ];
+    for policy in policies {
+        let resolved = resolve_metadata_policy(&map(policy), &metadata).unwrap();
+        assert_eq!(resolved, Value::Object(metadata.clone()));
+    }
+}
+
+#[test]
+fn essential_false_allows_a_missing_parameter() {
+    let policy = map(json!({"contacts": {"subset_of": ["a"], "essential": false}}));
+    let resolved = resolve_metadata_policy(&policy, &map(json!({}))).unwrap();
+    assert_eq!(resolved, json!({}));
+
+    let policy = map(json!({"contacts": {"essential": true}}));
+    let error = resolve_metadata_policy(&policy, &map(json!({}))).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "We have an essential policy but not metadata"
+    );
+}
+
+#[test]
+fn only_essential_true_requires_a_parameter() {
+    let cases = [
+        (json!({"essential": false}), Ok(json!({}))),
+        (
+            json!({"essential": false, "default": ["a"]}),
+            Ok(json!({"contacts": ["a"]})),
+        ),
+        (
+            json!({"essential": false, "subset_of": ["a"], "superset_of": ["a"]}),
+            Ok(json!({})),
+        ),
+        (
+            json!({"essential": true}),
+            Err("We have an essential policy but not metadata"),
+        ),
+        (
+            json!({"essential": true, "subset_of": ["a"]}),
+            Err("We have an essential policy but empty subset"),
+        ),
+    ];
+    for (policy, expected) in cases {
+        let policy = map(json!({"contacts": policy}));
+        let resolved = resolve_metadata_policy(&policy, &map(json!({})));
+        assert_eq!(
+            resolved.map_err(|e| e.to_string()),
+            expected.map_err(String::from)
+        );
+    }
+}
+
+#[test]
+fn operands_of_the_wrong_type_are_errors() {
+    let error = merge_policies(
+        &json!({"grant_types": {"subset_of": "a"}}),
+        &json!({"grant_types": {"subset_of": ["a"]}}),
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Policy error: grant_types: subset_of must be an array, not \"a\""
+    );
+}
+
+#[test]
+fn resolved_subset_of_keeps_the_metadata_order() {
+    let policy = map(json!({
+        "grant_types": {"subset_of": ["implicit", "refresh_token", "authorization_code"]},
+        "response_types": {"add": ["id_token", "code"], "subset_of": ["code", "id_token"]},
+    }));
+    let metadata = map(json!({
+        "grant_types": ["authorization_code", "password", "refresh_token"],
+    }));
+    for _ in 0..10 {
+        let resolved = resolve_metadata_policy(&policy, &metadata).unwrap();
+        assert_eq!(
+            resolved,
+            json!({
+                "grant_types": ["authorization_code", "refresh_token"],
+                "response_types": ["id_token", "code"],
+            })
+        );
+    }
+    let resolved =
+        resolve_metadata_policy(&policy, &map(json!({"grant_types": ["password"]}))).unwrap();
+    assert_eq!(resolved["grant_types"], json!([]));
+}
+
+#[test]
+fn parameters_from_one_policy_are_checked_too() {
+    let invalid =
+        json!({"grant_types": {"add": ["implicit"], "subset_of": ["authorization_code"]}});
+    for (superior, subordinate) in [(&invalid, &json!({})), (&json!({}), &invalid)] {
+        let err = merge_policies(superior, subordinate).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Subordinate policy merge error: the values of add must be a subset of the values of subset_of"
+        );
+    }
+}
+
+#[test]
+fn add_to_metadata_which_is_not_an_array_is_an_error() {
+    let policy = map(json!({"grant_types": {"add": ["authorization_code"]}}));
+    for value in [json!("x"), json!(1), json!(null), json!({"a": 1})] {
+        let metadata = map(json!({"grant_types": value}));
+        let error = resolve_metadata_policy(&policy, &metadata).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Policy error: grant_types: add needs an array in the metadata, not {}",
+                value
+            )
+        );
+    }
+}
@@ -0,0 +1,253 @@
+// Algebraic properties of merge_policies and their consistency with resolve_metadata_policy.
+use oidfed_metadata_policy::operators::broken_combination;
+use oidfed_metadata_policy::resolve_metadata_policy;
+use oidfed_metadata_policy::{check_equal, get_hashset_from_values, merge_policies};
+use proptest::prelude::*;
+use proptest::sample::subsequence;
+use serde_json::{Map, Value, json};
+
+// Parameters taking a list of values
+const ARRAY_PARAMS: [&str; 2] = ["grant_types", "contacts"];
+// Parameters taking a single string
+const SCALAR_PARAMS: [&str; 2] = ["token_endpoint_auth_method", "client_name"];
+const VALUES: [&str; 5] = ["a", "b", "c", "d", "e"];
+
+fn values() -> impl Strategy<Value = Vec<String>> {
+    subsequence(VALUES.to_vec(), 0..=VALUES.len())
+        .prop_shuffle()
+        .prop_map(|v| v.into_iter().map(String::from).collect())
+}
+
+fn non_empty_values() -> impl Strategy<Value = Vec<String>> {
+    subsequence(VALUES.to_vec(), 1..=VALUES.len())
+        .prop_shuffle()
+        .prop_map(|v| v.into_iter().map(String::from).collect())
+}
+
+fn scalar() -> impl Strategy<Value = Value> {
+    proptest::sample::select(VALUES.to_vec()).prop_map(|v| json!(v))
+}
+
+fn array_operators() -> impl Strategy<Value = Map<String, Value>> {
+    (
+        proptest::option::of(prop_oneof![
+            values().prop_map(|v| json!(v)),
+            Just(Value::Null)
+        ]),
+        proptest::option::of(values()),
+        proptest::option::of(values()),
+        proptest::option::of(values()),
+        proptest::option::of(values()),
+        proptest::option::of(any::<bool>()),
+    )
+        .prop_map(|(value, default, add, subset_of, superset_of, essential)| {
+            let mut ops = Map::new();
+            if let Some(v) = value {
+                ops.insert("value".to_string(), v);
+            }
+            if let Some(v) = default {
+                ops.insert("default".to_string(), json!(v));
+            }
+            if let Some(v) = add {
+                ops.insert("add".to_string(), json!(v));
+            }
+            if let Some(v) = subset_of {
+                ops.insert("subset_of".to_string(), json!(v));
+            }
+            if let Some(v) = superset_of {
+                ops.insert("superset_of".to_string(), json!(v));
+            }
+            if let Some(v) = essential {
+                ops.insert("essential".to_string(), json!(v));
+            }
+            ops
+        })
+}
+
+fn scalar_operators() -> impl Strategy<Value = Map<String, Value>> {
+    (
+        proptest::option::of(prop_oneof![scalar(), Just(Value::Null)]),
+        proptest::option::of(scalar()),
+        proptest::option::of(non_empty_values()),
+        proptest::option::of(any::<bool>()),
+    )
+        .prop_map(|(value, default, one_of, essential)| {
+            let mut ops = Map::new();
+            if let Some(v) = value {
+                ops.insert("value".to_string(), v);
+            }
+            if let Some(v) = default {
+                ops.insert("default".to_string(), v);
+            }
+            if let Some(v) = one_of {
+                ops.insert("one_of".to_string(), json!(v));
+            }
+            if let Some(v) = essential {
+                ops.insert("essential".to_string(), json!(v));
+            }
+            ops
+        })
+}
+
+fn policy() -> impl Strategy<Value = Map<String, Value>> {
+    (
+        proptest::collection::vec(proptest::option::of(array_operators()), ARRAY_PARAMS.len()),
+        proptest::collection::vec(
+            proptest::option::of(scalar_operators()),
+            SCALAR_PARAMS.len(),
+        ),
+    )
+        .prop_map(|(arrays, scalars)| {
+            let mut policy = Map::new();
+            for (name, ops) in ARRAY_PARAMS.iter().zip(arrays) {
+                if let Some(ops) = ops {
+                    policy.insert(name.to_string(), Value::Object(ops));
+                }
+            }
+            for (name, ops) in SCALAR_PARAMS.iter().zip(scalars) {
+                if let Some(ops) = ops {
+                    policy.insert(name.to_string(), Value::Object(ops));
+                }
+            }
+            policy
+        })
+}
+
+// Policies whose sequential application must agree with the merged policy: only the
+// operators that check or narrow a value which is present in the metadata.
+fn narrowing_policy() -> impl Strategy<Value = Map<String, Value>> {
+    (
+        proptest::option::of(values()),
+        proptest::option::of(values()),
+        proptest::option::of(non_empty_values()),
+        proptest::option::of(any::<bool>()),
+    )
+        .prop_map(|(subset_of, superset_of, one_of, essential)| {
+            let mut policy = Map::new();
+            let mut grant_types = Map::new();
+            if let Some(v) = subset_of {
+                grant_types.insert("subset_of".to_string(), json!(v));
+            }
+            if let Some(v) = superset_of {
+                grant_types.insert("superset_of".to_string(), json!(v));
+            }
+            if let Some(v) = essential {
+                grant_types.insert("essential".to_string(), json!(v));
+            }
+            policy.insert("grant_types".to_string(), Value::Object(grant_types));
+            let mut auth_method = Map::new();
+            if let Some(v) = one_of {
+                auth_method.insert("one_of".to_string(), json!(v));
+            }
+            policy.insert(
+                "token_endpoint_auth_method".to_string(),
+                Value::Object(auth_method),
+            );
+            policy
+        })
+}
+
+fn metadata() -> impl Strategy<Value = Map<String, Value>> {
+    (values(), scalar()).prop_map(|(grant_types, auth_method)| {
+        let mut metadata = Map::new();
+        metadata.insert("grant_types".to_string(), json!(grant_types));
+        metadata.insert("token_endpoint_auth_method".to_string(), auth_method);
+        metadata
+    })
+}
+
+fn operator<'a>(policy: &'a Map<String, Value>, param: &str, op: &str) -> Option<&'a Value> {
+    policy.get(param).and_then(|ops| ops.get(op))
+}
+
+proptest! {
+    #[test]
+    fn merge_with_empty_policy_is_identity(p in policy()) {
+        let empty = json!({});
+        let p_value = Value::Object(p.clone());
+        // A policy which breaks a combination rule is rejected even with nothing to merge
+        let valid = p
+            .values()
+            .all(|ops| broken_combination(ops.as_object().unwrap()).is_none());
+        if valid {
+            prop_assert_eq!(merge_policies(&p_value, &empty).unwrap(), p.clone());
+            prop_assert_eq!(merge_policies(&empty, &p_value).unwrap(), p);
+        } else {
+            prop_assert!(merge_policies(&p_value, &empty).is_err());
+            prop_assert!(merge_policies(&empty, &p_value).is_err());
+        }
+    }
+
+    #[test]
+    fn merge_is_at_least_as_restrictive_as_inputs(ta in policy(), ia in policy()) {
+        let Ok(merged) = merge_policies(&json!(ta), &json!(ia)) else {
+            return Ok(());
+        };
+        for input in [&ta, &ia] {
+            for (param, ops) in input.iter() {
+                for (op, value) in ops.as_object().unwrap().iter() {
+                    let merged_value = operator(&merged, param, op);
+                    prop_assert!(merged_value.is_some(), "{} lost {}", param, op);
+                    let merged_value = merged_value.unwrap();
+                    let merged_set = get_hashset_from_values(merged_value);
+                    let input_set = get_hashset_from_values(value);
+                    match op.as_str() {
+                        "value" | "default" => prop_assert_eq!(merged_value, value),
+                        "one_of" | "subset_of" => prop_assert!(merged_set.is_subset(&input_set)),
+                        "superset_of" | "add" => prop_assert!(merged_set.is_superset(&input_set)),
+                        "essential" => {
+                            prop_assert!(merged_value.as_bool().unwrap() >= value.as_bool().unwrap())
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn add_merge_is_order_preserving_union(ta_add in values(), ia_add in values()) {
+        let ta = json!({"contacts": {"add": ta_add}});
+        let ia = json!({"contacts": {"add": ia_add}});
+        let merged = merge_policies(&ta, &ia).unwrap();
+        let mut expected = ta_add.clone();
+        for v in ia_add.iter() {
+            if !expected.contains(v) {
+                expected.push(v.clone());
+            }
+        }
+        prop_assert_eq!(operator(&merged, "contacts", "add").unwrap(), &json!(expected));
+    }
+
+    #[test]
+    fn one_of_merge_never_grows(ta_one_of in non_empty_values(), ia_one_of in non_empty_values()) {
+        let ta = json!({"token_endpoint_auth_method": {"one_of": ta_one_of}});
+        let ia = json!({"token_endpoint_auth_method": {"one_of": ia_one_of}});
+        if let Ok(merged) = merge_policies(&ta, &ia) {
+            let merged_one_of =
+                get_hashset_from_values(operator(&merged, "token_endpoint_auth_method", "one_of").unwrap());
+            prop_assert!(merged_one_of.is_subset(&get_hashset_from_values(&json!(ta_one_of))));
+            prop_assert!(merged_one_of.is_subset(&get_hashset_from_values(&json!(ia_one_of))));
+        }
+    }
+
+    #[test]
+    fn merged_resolution_matches_sequential_resolution(
+        ta in narrowing_policy(),
+        ia in narrowing_policy(),
+        md in metadata(),
+    ) {
+        let Ok(merged) = merge_policies(&json!(ta), &json!(ia)) else {
+            return Ok(());
+        };
+        let from_merged = resolve_metadata_policy(&merged, &md);
+        let sequential = resolve_metadata_policy(&ta, &md).and_then(|after_ta| {
+            resolve_metadata_policy(&ia, after_ta.as_object().unwrap())
+        });
+        match (from_merged, sequential) {
+            (Ok(m), Ok(s)) => prop_assert!(check_equal(&m, &s), "merged {} sequential {}", m, s),
+            (Err(_), Err(_)) => (),
+            (m, s) => prop_assert!(false, "merged {:?} sequential {:?}", m, s),
+        }
+    }
+}
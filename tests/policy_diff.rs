@@ -0,0 +1,115 @@
+mod common;
+
+use common::map;
+use oidfed_metadata_policy::policy_diff::{
+    ChangeKind, Effect, diff_metadata_policies, diff_policies,
+};
+use serde_json::json;
+
+#[test]
+fn classifies_value_changes() {
+    let old = map(json!({
+        "grant_types": {"subset_of": ["authorization_code", "implicit"], "superset_of": ["authorization_code"]},
+        "scope": {"add": ["openid"], "default": ["openid"]},
+    }));
+    let new = map(json!({
+        "grant_types": {"subset_of": ["authorization_code", "refresh_token"], "superset_of": ["authorization_code"]},
+        "scope": {"add": ["openid", "email"], "default": ["openid", "email"]},
+    }));
+    let changes: Vec<(String, String, ChangeKind, Effect)> = diff_policies(&old, &new)
+        .into_iter()
+        .map(|c| (c.parameter, c.operator, c.kind, c.effect))
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            (
+                "grant_types".to_string(),
+                "subset_of".to_string(),
+                ChangeKind::ValuesAdded(vec![json!("refresh_token")]),
+                Effect::Loosens
+            ),
+            (
+                "grant_types".to_string(),
+                "subset_of".to_string(),
+                ChangeKind::ValuesRemoved(vec![json!("implicit")]),
+                Effect::Tightens
+            ),
+            (
+                "scope".to_string(),
+                "add".to_string(),
+                ChangeKind::ValuesAdded(vec![json!("email")]),
+                Effect::Tightens
+            ),
+            (
+                "scope".to_string(),
+                "default".to_string(),
+                ChangeKind::ValueChanged {
+                    old: json!(["openid"]),
+                    new: json!(["openid", "email"])
+                },
+                Effect::Neutral
+            ),
+        ]
+    );
+}
+
+#[test]
+fn reports_operators_and_entity_types() {
+    let old = map(json!({
+        "openid_relying_party": {"token_endpoint_auth_method": {"one_of": ["private_key_jwt"], "essential": true}},
+    }));
+    let new = map(json!({
+        "openid_relying_party": {"token_endpoint_auth_method": {"one_of": ["private_key_jwt"]}},
+        "federation_entity": {"contacts": {"essential": true}},
+    }));
+    let changes = diff_metadata_policies(&old, &new);
+    let lines: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "openid_relying_party.token_endpoint_auth_method essential: removed true (loosens)",
+            "federation_entity.contacts essential: added true (tightens)",
+        ]
+    );
+    assert_eq!(changes[1].to_json()["kind"], "operator_added");
+    assert!(diff_metadata_policies(&old, &old).is_empty());
+}
+
+#[test]
+fn operands_of_the_wrong_type_are_reported_as_changed_values() {
+    let old = map(json!({"grant_types": {"subset_of": ["authorization_code"]}}));
+    let new = map(json!({"grant_types": {"subset_of": "authorization_code"}}));
+    let changes = diff_policies(&old, &new);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(
+        changes[0].kind,
+        ChangeKind::ValueChanged {
+            old: json!(["authorization_code"]),
+            new: json!("authorization_code")
+        }
+    );
+    assert_eq!(changes[0].effect, Effect::Neutral);
+}
+
+#[test]
+fn policies_which_are_not_objects_have_no_operators() {
+    let old = map(json!({"grant_types": {"essential": true}}));
+    let new = map(json!({"grant_types": ["essential"]}));
+    let lines: Vec<String> = diff_policies(&old, &new)
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    assert_eq!(lines, vec!["grant_types essential: removed true (loosens)"]);
+
+    let old = map(json!({"openid_relying_party": "none"}));
+    let new = map(json!({"openid_relying_party": {"scope": {"value": "openid"}}}));
+    let lines: Vec<String> = diff_metadata_policies(&old, &new)
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    assert_eq!(
+        lines,
+        vec![r#"openid_relying_party.scope value: added "openid" (tightens)"#]
+    );
+}
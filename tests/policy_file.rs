@@ -0,0 +1,141 @@
+use oidfed_metadata_policy::merge_metadata_policies;
+use oidfed_metadata_policy::policy_file::{PolicyFile, merge_policy_files, read_policy};
+use serde_json::{Value, json};
+
+use std::path::PathBuf;
+
+// Writes the documents to a new directory, in order
+fn files(name: &str, documents: &[(&str, &str)]) -> Vec<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("oidfed-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    documents
+        .iter()
+        .map(|(file, text)| {
+            let path = dir.join(file);
+            std::fs::write(&path, text).unwrap();
+            path
+        })
+        .collect()
+}
+
+#[test]
+fn merges_several_bare_policies_in_order() {
+    let paths = files(
+        "bare",
+        &[
+            (
+                "ta.json",
+                r#"{"grant_types": {"subset_of": ["a", "b", "c"]}}"#,
+            ),
+            ("ia.yaml", "grant_types:\n  subset_of: [c, b]\n"),
+            ("leaf.toml", "[contacts]\nadd = [\"ops@example.org\"]\n"),
+        ],
+    );
+    let merged = merge_policy_files(&paths).unwrap();
+    assert_eq!(
+        merged,
+        PolicyFile::Bare(json!({
+            "contacts": {"add": ["ops@example.org"]},
+            "grant_types": {"subset_of": ["b", "c"]},
+        }))
+    );
+}
+
+#[test]
+fn merges_metadata_policy_claims() {
+    let ta = json!({"openid_relying_party": {"grant_types": {"subset_of": ["a", "b"]}}});
+    let ia = json!({
+        "openid_relying_party": {"grant_types": {"subset_of": ["b"]}},
+        "federation_entity": {"contacts": {"add": ["ops@example.org"]}},
+    });
+    let expected = json!({
+        "federation_entity": {"contacts": {"add": ["ops@example.org"]}},
+        "openid_relying_party": {"grant_types": {"subset_of": ["b"]}},
+    });
+    assert_eq!(
+        Value::Object(merge_metadata_policies(&ta, &ia).unwrap()),
+        expected
+    );
+
+    let paths = files(
+        "claims",
+        &[
+            (
+                "ta.json",
+                &json!({"iss": "ta", "metadata_policy": ta}).to_string(),
+            ),
+            ("ia.json", &json!({"metadata_policy": ia}).to_string()),
+        ],
+    );
+    let merged = merge_policy_files(&paths).unwrap();
+    assert_eq!(merged.into_document(), json!({"metadata_policy": expected}));
+
+    let error = merge_metadata_policies(&ta, &json!({"openid_relying_party": []})).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Policy error: policy for openid_relying_party must be a JSON object"
+    );
+}
+
+#[test]
+fn bare_policies_and_claims_can_not_be_mixed() {
+    let paths = files(
+        "mixed",
+        &[
+            ("ta.json", r#"{"grant_types": {"subset_of": ["a"]}}"#),
+            (
+                "ia.json",
+                r#"{"metadata_policy": {"openid_relying_party": {"grant_types": {"subset_of": ["a"]}}}}"#,
+            ),
+        ],
+    );
+    let error = merge_policy_files(&paths).unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        format!(
+            "Failed to merge {}: Policy error: bare policies and metadata_policy claims can not be mixed",
+            paths[1].display()
+        )
+    );
+}
+
+#[test]
+fn a_single_file_is_checked() {
+    let paths = files(
+        "single",
+        &[
+            (
+                "ok.json",
+                r#"{"grant_types": {"subset_of": ["a"], "default": ["a"]}}"#,
+            ),
+            ("operand.json", r#"{"grant_types": {"subset_of": "a"}}"#),
+            ("shape.json", r#"{"grant_types": ["a"]}"#),
+        ],
+    );
+    assert!(matches!(
+        read_policy(&paths[0]).unwrap(),
+        PolicyFile::Bare(_)
+    ));
+    merge_policy_files(&paths[..1]).unwrap();
+
+    let error = merge_policy_files(&paths[1..2]).unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        format!(
+            "Invalid policy in {}: Policy error: grant_types: subset_of must be an array, not \"a\"",
+            paths[1].display()
+        )
+    );
+    let error = merge_policy_files(&paths[2..]).unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        format!(
+            "{} is not a valid metadata policy: Policy error: every parameter must have a JSON object of operators",
+            paths[2].display()
+        )
+    );
+    assert_eq!(
+        merge_policy_files(&[]).unwrap_err().to_string(),
+        "No policy files given"
+    );
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::map;
+use oidfed_metadata_policy::remediation::{
+    Fix, apply_fixes, suggest_fixes, suggest_metadata_fixes,
+};
+use oidfed_metadata_policy::resolve_metadata_policy;
+use serde_json::json;
+
+#[test]
+fn suggested_fixes_make_the_metadata_pass() {
+    let policy = map(json!({
+        "token_endpoint_auth_method": {"one_of": ["private_key_jwt", "self_signed_tls_client_auth"], "default": "self_signed_tls_client_auth"},
+        "grant_types": {"add": ["refresh_token"], "superset_of": ["authorization_code", "refresh_token"]},
+        "contacts": {"essential": true},
+        "response_types": {"subset_of": ["code"], "essential": true},
+        "client_name": {"essential": true},
+    }));
+    let metadata = map(json!({
+        "token_endpoint_auth_method": "client_secret_basic",
+        "grant_types": ["implicit"],
+        "client_name": "Example",
+    }));
+    assert!(resolve_metadata_policy(&policy, &metadata).is_err());
+
+    let suggestions = suggest_fixes(&policy, &metadata);
+    let fixes: Vec<(&str, &Fix)> = suggestions
+        .iter()
+        .map(|s| (s.parameter.as_str(), &s.fix))
+        .collect();
+    assert_eq!(
+        fixes,
+        vec![
+            ("contacts", &Fix::SupplyParameter),
+            (
+                "grant_types",
+                &Fix::AddValues(vec![json!("authorization_code")])
+            ),
+            ("response_types", &Fix::SetValue(json!(["code"]))),
+            (
+                "token_endpoint_auth_method",
+                &Fix::SetValue(json!("self_signed_tls_client_auth"))
+            ),
+        ]
+    );
+
+    let mut fixed = apply_fixes(&metadata, &suggestions);
+    fixed.insert("contacts".to_string(), json!(["ops@example.com"]));
+    assert!(resolve_metadata_policy(&policy, &fixed).is_ok());
+}
+
+#[test]
+fn reports_contradicting_policies() {
+    let policy = map(json!({
+        "openid_relying_party": {
+            "grant_types": {"subset_of": ["authorization_code"], "superset_of": ["refresh_token"]},
+        },
+    }));
+    let metadata = map(json!({
+        "openid_relying_party": {"grant_types": ["authorization_code"]},
+    }));
+    let suggestions = suggest_metadata_fixes(&policy, &metadata);
+    assert_eq!(suggestions.len(), 1);
+    assert!(matches!(suggestions[0].fix, Fix::Unfixable(_)));
+    assert_eq!(
+        suggestions[0].entity_type.as_deref(),
+        Some("openid_relying_party")
+    );
+    assert_eq!(suggestions[0].to_json()["fix"], "unfixable");
+}
+
+#[test]
+fn an_invalid_operator_value_can_not_be_fixed() {
+    let policy = map(json!({"scope": {"one_of": "openid"}}));
+    let metadata = map(json!({"scope": "email"}));
+    let suggestions = suggest_fixes(&policy, &metadata);
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(
+        suggestions[0].problem,
+        r#"Policy error: scope: one_of must be an array, not "openid""#
+    );
+    assert_eq!(
+        suggestions[0].fix,
+        Fix::Unfixable("no operator to satisfy".to_string())
+    );
+    assert_eq!(apply_fixes(&metadata, &suggestions), metadata);
+}
+
+#[test]
+fn empty_operands_can_not_be_fixed() {
+    let policy = map(json!({
+        "grant_types": {"subset_of": [], "essential": true},
+        "scope": {"one_of": [], "essential": true},
+    }));
+    let suggestions = suggest_fixes(&policy, &map(json!({})));
+    let fixes: Vec<String> = suggestions.iter().map(|s| s.to_string()).collect();
+    assert_eq!(
+        fixes,
+        vec![
+            "grant_types: We have an essential policy but empty subset, can not be fixed in the metadata: subset_of is empty",
+            "scope: We have an essential policy but not metadata, can not be fixed in the metadata: one_of is empty",
+        ]
+    );
+}
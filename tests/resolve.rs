@@ -0,0 +1,181 @@
+mod common;
+
+use common::{Entity, TestKey};
+use oidfed_metadata_policy::chain::{TrustAnchor, ValidatedChain, validate_trust_chain};
+use oidfed_metadata_policy::jws::{Jws, JwsSigner};
+use oidfed_metadata_policy::resolve::{RESOLVE_RESPONSE_TYPE, ResolveResponse};
+use serde_json::json;
+
+#[test]
+fn produces_signed_resolve_responses() {
+    let ta = Entity {
+        id: "https://ta.example.org",
+        key: TestKey::ed(20),
+        alg: "EdDSA",
+    };
+    let rp = Entity {
+        id: "https://rp.example.org",
+        key: TestKey::ec(21),
+        alg: "ES256",
+    };
+    let trust_mark =
+        json!({"trust_mark_type": "https://ta.example.org/certified", "trust_mark": "a.b.c"});
+    let tokens = [
+        rp.statement(
+            &rp,
+            1700086400,
+            json!({
+                "authority_hints": [ta.id],
+                "metadata": {"openid_relying_party": {"scope": "openid email"}},
+                "trust_marks": [trust_mark],
+            }),
+        ),
+        ta.statement(
+            &rp,
+            1700040000,
+            json!({"metadata_policy": {"openid_relying_party": {"scope": {"value": "openid"}}}}),
+        ),
+        ta.statement(&ta, 1700090000, json!({})),
+    ];
+    let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+    let anchor = TrustAnchor {
+        entity_id: ta.id.to_string(),
+        jwks: ta.jwks(),
+    };
+    let chain = validate_trust_chain(&tokens, &anchor, 1700000100).unwrap();
+
+    let resolver = "https://resolver.example.org";
+    let response = ResolveResponse::from_chain(resolver, &chain, 1700000200).unwrap();
+    let signer = JwsSigner::from_jwk(&TestKey::ed(22).private_jwk(resolver), "EdDSA").unwrap();
+    let token = response.sign(&signer).unwrap();
+
+    let jws = Jws::parse(&token).unwrap();
+    jws.check_typ(RESOLVE_RESPONSE_TYPE).unwrap();
+    jws.verify(&json!({"keys": [signer.public_jwk()]})).unwrap();
+    assert_eq!(
+        jws.payload,
+        json!({
+            "iss": resolver,
+            "sub": "https://rp.example.org",
+            "iat": 1700000200,
+            "exp": 1700040000,
+            "metadata": {"openid_relying_party": {"scope": "openid"}},
+            // The trust marks were never checked
+            "trust_chain": tokens,
+        })
+    );
+
+    let error = ResolveResponse::from_chain(resolver, &chain, 1700040000).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Resolve error: the trust chain has expired"
+    );
+}
+
+const RESOLVER: &str = "https://resolver.example.org";
+
+// A chain rp -> ia -> ta, the statement of the intermediate about the leaf expires first
+fn three_level_chain() -> (ValidatedChain, Vec<String>) {
+    let ta = Entity {
+        id: "https://ta.example.org",
+        key: TestKey::ed(30),
+        alg: "EdDSA",
+    };
+    let ia = Entity {
+        id: "https://ia.example.org",
+        key: TestKey::rsa(),
+        alg: "PS256",
+    };
+    let rp = Entity {
+        id: "https://rp.example.org",
+        key: TestKey::ec(31),
+        alg: "ES256",
+    };
+    let tokens = vec![
+        rp.statement(
+            &rp,
+            1700090000,
+            json!({
+                "authority_hints": [ia.id],
+                "metadata": {"openid_relying_party": {"contacts": ["rp@example.org"]}},
+            }),
+        ),
+        ia.statement(
+            &rp,
+            1700030000,
+            json!({"metadata_policy": {"openid_relying_party": {"contacts": {"add": ["ia@example.org"]}}}}),
+        ),
+        ta.statement(
+            &ia,
+            1700080000,
+            json!({"metadata_policy": {"openid_relying_party": {"contacts": {"add": ["ta@example.org"]}}}}),
+        ),
+        ta.statement(&ta, 1700070000, json!({})),
+    ];
+    let anchor = TrustAnchor {
+        entity_id: ta.id.to_string(),
+        jwks: ta.jwks(),
+    };
+    let refs: Vec<&str> = tokens.iter().map(String::as_str).collect();
+    let chain = validate_trust_chain(&refs, &anchor, 1700000100).unwrap();
+    (chain, tokens)
+}
+
+#[test]
+fn response_claims_come_from_the_chain() {
+    let (chain, tokens) = three_level_chain();
+    let response = ResolveResponse::from_chain(RESOLVER, &chain, 1700000200).unwrap();
+    assert_eq!(response.iss, RESOLVER);
+    assert_eq!(response.sub, "https://rp.example.org");
+    assert_eq!(response.iat, 1700000200);
+    assert_eq!(chain.expires_at, 1700030000);
+    assert_eq!(response.exp, chain.expires_at);
+    assert_eq!(
+        serde_json::Value::Object(response.metadata.clone()),
+        json!({"openid_relying_party": {"contacts": ["rp@example.org", "ta@example.org", "ia@example.org"]}})
+    );
+    assert_eq!(response.trust_chain, tokens);
+    assert_eq!(response.trust_marks, None);
+
+    let claims = response.to_claims();
+    assert_eq!(claims["exp"], 1700030000);
+    assert_eq!(claims["trust_chain"], json!(tokens));
+    assert!(claims.get("trust_marks").is_none());
+}
+
+#[test]
+fn signed_response_verifies_with_the_resolver_keys() {
+    let (chain, _) = three_level_chain();
+    let response = ResolveResponse::from_chain(RESOLVER, &chain, 1700000200).unwrap();
+    let signer = JwsSigner::from_jwk(&TestKey::ec(32).private_jwk(RESOLVER), "ES256").unwrap();
+    let token = response.sign(&signer).unwrap();
+
+    let jws = Jws::parse(&token).unwrap();
+    jws.check_typ(RESOLVE_RESPONSE_TYPE).unwrap();
+    assert_eq!(jws.header["kid"], RESOLVER);
+    assert_eq!(jws.header["alg"], "ES256");
+    jws.verify(&json!({"keys": [signer.public_jwk()]})).unwrap();
+    assert_eq!(jws.payload, response.to_claims());
+
+    // A key with the same kid but from another resolver
+    let other = TestKey::ec(33).jwk(RESOLVER);
+    let error = jws.verify(&json!({"keys": [other]})).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "JWS error: the signature does not verify with key https://resolver.example.org"
+    );
+    assert!(jws.check_typ("entity-statement+jwt").is_err());
+}
+
+#[test]
+fn expired_chains_have_no_response() {
+    let (chain, _) = three_level_chain();
+    assert!(ResolveResponse::from_chain(RESOLVER, &chain, 1700029999).is_ok());
+    for now in [1700030000, 1700050000] {
+        let error = ResolveResponse::from_chain(RESOLVER, &chain, now).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Resolve error: the trust chain has expired"
+        );
+    }
+}
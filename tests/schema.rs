@@ -0,0 +1,39 @@
+use oidfed_metadata_policy::schema::metadata_policy_schema;
+use oidfed_metadata_policy::{merge_policies, resolve_metadata_policy};
+use serde_json::{Value, json};
+
+#[test]
+fn shipped_schema_is_up_to_date() {
+    let text = std::fs::read_to_string("schema/metadata-policy.schema.json").unwrap();
+    let shipped: Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(
+        shipped,
+        metadata_policy_schema(),
+        "regenerate with: cargo run -- schema > schema/metadata-policy.schema.json"
+    );
+}
+
+#[test]
+fn operand_types_are_checked() {
+    let error = merge_policies(
+        &json!({"grant_types": {"subset_of": "authorization_code"}}),
+        &json!({}),
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Policy error: grant_types: subset_of must be an array, not \"authorization_code\""
+    );
+
+    let policy = json!({"contacts": {"essential": "yes"}});
+    let error =
+        resolve_metadata_policy(policy.as_object().unwrap(), &serde_json::Map::new()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Policy error: contacts: essential must be a boolean, not \"yes\""
+    );
+
+    // Operators this crate does not know are left alone
+    let policy = json!({"contacts": {"regexp": "^ops@"}});
+    assert!(merge_policies(&policy, &json!({})).is_ok());
+}
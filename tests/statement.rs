@@ -0,0 +1,148 @@
+use oidfed_metadata_policy::statement::{
+    EntityConfiguration, EntityStatement, apply_superior_metadata, merge_statement_policies,
+};
+use serde_json::{Value, json};
+
+fn subordinate_statement(iss: &str, sub: &str, policy: Value) -> Value {
+    json!({
+        "iss": iss,
+        "sub": sub,
+        "iat": 1700000000,
+        "exp": 1700086400,
+        "jwks": {"keys": []},
+        "metadata_policy": policy,
+    })
+}
+
+#[test]
+fn reads_an_entity_configuration() {
+    let claims = json!({
+        "iss": "https://rp.example.org",
+        "sub": "https://rp.example.org",
+        "iat": 1700000000,
+        "exp": 1700086400,
+        "jwks": {"keys": [{"kty": "OKP", "crv": "Ed25519", "x": "abc", "kid": "1"}]},
+        "authority_hints": ["https://ia.example.org"],
+        "metadata": {"openid_relying_party": {"grant_types": ["authorization_code"]}},
+        "x_extension": 1,
+    });
+    let configuration = EntityConfiguration::from_claims(&claims).unwrap();
+    assert_eq!(
+        configuration.authority_hints,
+        Some(vec!["https://ia.example.org".to_string()])
+    );
+    assert_eq!(configuration.other["x_extension"], 1);
+    assert_eq!(configuration.to_claims(), claims);
+
+    let statement = subordinate_statement(
+        "https://ia.example.org",
+        "https://rp.example.org",
+        json!({}),
+    );
+    assert!(EntityConfiguration::from_claims(&statement).is_err());
+}
+
+#[test]
+fn rejects_invalid_claims() {
+    let error = |claims: Value| {
+        EntityStatement::from_claims(&claims)
+            .unwrap_err()
+            .to_string()
+    };
+    let mut claims = subordinate_statement(
+        "https://ia.example.org",
+        "https://rp.example.org",
+        json!({}),
+    );
+    claims.as_object_mut().unwrap().remove("exp");
+    assert_eq!(error(claims), "Entity statement error: exp is missing");
+
+    let mut claims = subordinate_statement(
+        "https://ia.example.org",
+        "https://rp.example.org",
+        json!({}),
+    );
+    claims["authority_hints"] = json!(["https://ta.example.org"]);
+    assert_eq!(
+        error(claims),
+        "Entity statement error: authority_hints is not allowed in a subordinate statement"
+    );
+
+    let mut claims = subordinate_statement(
+        "https://ia.example.org",
+        "https://rp.example.org",
+        json!({}),
+    );
+    claims["metadata_policy_crit"] = json!(["one_of"]);
+    assert_eq!(
+        error(claims),
+        "Entity statement error: metadata_policy_crit can not list one_of"
+    );
+}
+
+#[test]
+fn merges_the_policies_of_a_chain() {
+    let ta = EntityStatement::from_claims(&subordinate_statement(
+        "https://ta.example.org",
+        "https://ia.example.org",
+        json!({"openid_relying_party": {"grant_types": {"subset_of": ["authorization_code", "refresh_token"]}}}),
+    ))
+    .unwrap();
+    let mut ia_claims = subordinate_statement(
+        "https://ia.example.org",
+        "https://rp.example.org",
+        json!({"openid_relying_party": {"grant_types": {"subset_of": ["authorization_code"]}, "scope": {"regexp": "^openid"}}}),
+    );
+    let ia = EntityStatement::from_claims(&ia_claims).unwrap();
+    let merged = merge_statement_policies(&[ta.clone(), ia]).unwrap();
+    assert_eq!(
+        merged["openid_relying_party"]["grant_types"],
+        json!({"subset_of": ["authorization_code"]})
+    );
+
+    ia_claims["metadata_policy_crit"] = json!(["regexp"]);
+    let ia = EntityStatement::from_claims(&ia_claims).unwrap();
+    let error = merge_statement_policies(&[ta, ia]).unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        "Failed to merge the policy of https://ia.example.org: Policy error: openid_relying_party.scope uses the critical operator regexp which is not supported"
+    );
+}
+
+#[test]
+fn applies_superior_metadata() {
+    let superior = EntityStatement::from_claims(&json!({
+        "iss": "https://ia.example.org",
+        "sub": "https://rp.example.org",
+        "iat": 1700000000,
+        "exp": 1700086400,
+        "jwks": {"keys": []},
+        "metadata": {
+            "openid_relying_party": {"client_name": "RP", "contacts": ["ops@example.org"]},
+            "federation_entity": {"organization_name": "Example"},
+        },
+    }))
+    .unwrap();
+    let leaf =
+        json!({"openid_relying_party": {"client_name": "RP", "contacts": ["rp@example.org"]}});
+    let (metadata, overrides) =
+        apply_superior_metadata(leaf.as_object().unwrap(), &superior).unwrap();
+    assert_eq!(
+        Value::Object(metadata),
+        json!({
+            "federation_entity": {"organization_name": "Example"},
+            "openid_relying_party": {"client_name": "RP", "contacts": ["ops@example.org"]},
+        })
+    );
+    let overrides: Vec<(&str, Option<&Value>)> = overrides
+        .iter()
+        .map(|o| (o.parameter.as_str(), o.before.as_ref()))
+        .collect();
+    assert_eq!(
+        overrides,
+        [
+            ("organization_name", None),
+            ("contacts", Some(&json!(["rp@example.org"])))
+        ]
+    );
+}
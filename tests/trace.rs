@@ -0,0 +1,180 @@
+// The trace and diff of a resolution, and resolving every entity type of the metadata.
+mod common;
+
+use common::map;
+use oidfed_metadata_policy::resolve_metadata_policies;
+use oidfed_metadata_policy::trace::{ParameterChange, diff_metadata, trace_resolution};
+use serde_json::{Value, json};
+
+fn reasons<'a>(changes: &'a [ParameterChange], parameter: &str) -> &'a [String] {
+    &changes
+        .iter()
+        .find(|c| c.parameter == parameter)
+        .unwrap()
+        .reasons
+}
+
+#[test]
+fn subset_of_after_add_reports_the_removed_value() {
+    let changes = trace_resolution(
+        &map(json!({"grant_types": {"add": ["b"], "subset_of": ["a"]}})),
+        &map(json!({"grant_types": ["a"]})),
+    )
+    .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert!(!changes[0].changed());
+    assert_eq!(
+        changes[0].reasons,
+        [
+            r#"add: added ["b"]"#,
+            r#"subset_of: removed ["b"] not in ["a"]"#
+        ]
+    );
+}
+
+#[test]
+fn reasons_follow_the_order_the_operators_are_applied() {
+    let changes = trace_resolution(
+        &map(json!({
+            "client_name": {"value": null},
+            "contacts": {"default": ["ops@example.org"], "essential": true},
+            "grant_types": {"essential": false, "superset_of": ["a"], "subset_of": ["a", "b"]},
+            "scope": {"one_of": ["openid", "email"], "subset_of": ["openid"]},
+        })),
+        &map(json!({
+            "client_name": "Example",
+            "grant_types": ["c", "b", "a"],
+            "scope": "email",
+        })),
+    )
+    .unwrap();
+    assert_eq!(
+        reasons(&changes, "client_name"),
+        ["value: null removes the parameter"]
+    );
+    assert_eq!(
+        reasons(&changes, "contacts"),
+        [
+            r#"default: not in the metadata, set to ["ops@example.org"]"#,
+            "essential: the parameter must be present"
+        ]
+    );
+    assert_eq!(
+        reasons(&changes, "grant_types"),
+        [
+            r#"subset_of: removed ["c"] not in ["a","b"]"#,
+            r#"superset_of: contains every value of ["a"]"#,
+            "essential: the parameter is optional"
+        ]
+    );
+    assert_eq!(
+        reasons(&changes, "scope"),
+        [
+            r#"one_of: "email" is one of ["openid","email"]"#,
+            "subset_of: not applied after one_of"
+        ]
+    );
+    let contacts = changes.iter().find(|c| c.parameter == "contacts").unwrap();
+    assert_eq!(contacts.before, None);
+    assert_eq!(contacts.after, Some(json!(["ops@example.org"])));
+}
+
+#[test]
+fn parameters_missing_from_the_metadata() {
+    let changes = trace_resolution(
+        &map(json!({
+            "contacts": {"add": ["ops@example.org"], "default": ["x@example.org"]},
+            "grant_types": {"subset_of": ["a"], "superset_of": ["a"]},
+            "scope": {"one_of": ["openid"]},
+        })),
+        &map(json!({})),
+    )
+    .unwrap();
+    assert_eq!(
+        reasons(&changes, "contacts"),
+        [
+            r#"add: not in the metadata, set to ["ops@example.org"]"#,
+            "default: not used"
+        ]
+    );
+    assert_eq!(
+        reasons(&changes, "grant_types"),
+        [
+            "subset_of: not in the metadata, the parameter stays absent",
+            "superset_of: the parameter is absent, nothing to check"
+        ]
+    );
+    assert_eq!(reasons(&changes, "scope"), ["one_of: nothing to check"]);
+}
+
+#[test]
+fn trace_fails_like_the_resolution() {
+    let err = trace_resolution(
+        &map(json!({"scope": {"one_of": ["openid"]}})),
+        &map(json!({"scope": "email"})),
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Failed to find in one_of");
+}
+
+#[test]
+fn diff_lists_only_changed_parameters() {
+    let changes = diff_metadata(
+        &map(json!({"a": 1, "b": 2, "c": 3})),
+        &map(json!({"a": 1, "b": 5, "d": 4})),
+    );
+    let lines: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+    assert_eq!(lines, ["~ b: 2 -> 5", "- c: 3", "+ d: 4"]);
+    assert!(changes.iter().all(|c| c.reasons.is_empty()));
+}
+
+#[test]
+fn resolves_each_entity_type_with_its_policy() {
+    let resolved = resolve_metadata_policies(
+        &map(json!({
+            "openid_relying_party": {"grant_types": {"subset_of": ["authorization_code"]}},
+        })),
+        &map(json!({
+            "federation_entity": {"organization_name": "Example"},
+            "openid_relying_party": {"grant_types": ["authorization_code", "implicit"]},
+        })),
+    )
+    .unwrap();
+    assert_eq!(
+        Value::Object(resolved),
+        json!({
+            "federation_entity": {"organization_name": "Example"},
+            "openid_relying_party": {"grant_types": ["authorization_code"]},
+        })
+    );
+}
+
+#[test]
+fn resolve_metadata_policies_errors() {
+    let err = resolve_metadata_policies(&map(json!({})), &map(json!({"openid_relying_party": []})))
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Metadata for openid_relying_party must be a JSON object"
+    );
+
+    let err = resolve_metadata_policies(
+        &map(json!({"openid_relying_party": []})),
+        &map(json!({"openid_relying_party": {}})),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Policy error: policy for openid_relying_party must be a JSON object"
+    );
+
+    let err = resolve_metadata_policies(
+        &map(json!({"openid_relying_party": {"scope": {"one_of": ["openid"]}}})),
+        &map(json!({"openid_relying_party": {"scope": "email"}})),
+    )
+    .unwrap_err();
+    assert_eq!(
+        format!("{:#}", err),
+        "Failed to resolve the openid_relying_party metadata: Failed to find in one_of"
+    );
+}
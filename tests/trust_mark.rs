@@ -0,0 +1,440 @@
+mod common;
+
+use common::{Entity, TestKey, sign_jwt};
+use oidfed_metadata_policy::chain::{TrustAnchor, ValidatedChain, validate_trust_chain};
+use oidfed_metadata_policy::resolve::ResolveResponse;
+use oidfed_metadata_policy::store::{MemoryStore, StatementStore};
+use oidfed_metadata_policy::trust_mark::TrustMarkChecker;
+use serde_json::{Value, json};
+
+use std::cell::RefCell;
+
+const NOW: i64 = 1700000100;
+const CERTIFIED: &str = "https://ta.example.org/certified";
+const OWNED: &str = "https://ta.example.org/owned";
+
+#[test]
+fn checks_the_trust_marks_of_the_leaf() {
+    let ta = Entity {
+        id: "https://ta.example.org",
+        key: TestKey::ed(30),
+        alg: "EdDSA",
+    };
+    let tmi = Entity {
+        id: "https://tmi.example.org",
+        key: TestKey::ec(31),
+        alg: "ES256",
+    };
+    let owner = Entity {
+        id: "https://owner.example.org",
+        key: TestKey::ed(32),
+        alg: "EdDSA",
+    };
+    let rp = Entity {
+        id: "https://rp.example.org",
+        key: TestKey::ed(33),
+        alg: "EdDSA",
+    };
+    let trust_mark = |issuer: &Entity, claims: Value| {
+        let mut all = json!({"iss": issuer.id, "sub": rp.id, "iat": 1700000000});
+        for (name, value) in claims.as_object().unwrap().iter() {
+            all[name] = value.clone();
+        }
+        let token = sign_jwt(&issuer.key, issuer.alg, issuer.id, "trust-mark+jwt", &all);
+        json!({"trust_mark_type": all["trust_mark_type"], "trust_mark": token})
+    };
+    let delegation = |to: &Entity| {
+        let claims = json!({
+            "iss": owner.id,
+            "sub": to.id,
+            "trust_mark_type": OWNED,
+            "iat": 1700000000,
+        });
+        sign_jwt(
+            &owner.key,
+            owner.alg,
+            owner.id,
+            "trust-mark-delegation+jwt",
+            &claims,
+        )
+    };
+    let trust_marks = vec![
+        trust_mark(&tmi, json!({"trust_mark_type": CERTIFIED})),
+        trust_mark(
+            &tmi,
+            json!({"trust_mark_type": CERTIFIED, "exp": 1700000050}),
+        ),
+        trust_mark(&rp, json!({"trust_mark_type": CERTIFIED})),
+        trust_mark(
+            &tmi,
+            json!({"trust_mark_type": OWNED, "delegation": delegation(&tmi)}),
+        ),
+        trust_mark(
+            &ta,
+            json!({"trust_mark_type": OWNED, "delegation": delegation(&rp)}),
+        ),
+        trust_mark(
+            &ta,
+            json!({"trust_mark_type": "https://unknown.example.org"}),
+        ),
+    ];
+
+    let rp_configuration = rp.statement(
+        &rp,
+        1700086400,
+        json!({
+            "authority_hints": [ta.id],
+            "metadata": {"openid_relying_party": {"client_name": "RP"}},
+            "trust_marks": trust_marks,
+        }),
+    );
+    let ta_configuration = ta.statement(
+        &ta,
+        1700086400,
+        json!({
+            "trust_mark_issuers": {CERTIFIED: [tmi.id], OWNED: []},
+            "trust_mark_owners": {OWNED: {"sub": owner.id, "jwks": owner.jwks()}},
+        }),
+    );
+    let about_rp = ta.statement(&rp, 1700086400, json!({}));
+    let mut store = MemoryStore::new();
+    for token in [
+        ta_configuration.clone(),
+        ta.statement(&tmi, 1700086400, json!({})),
+        tmi.statement(
+            &tmi,
+            1700086400,
+            json!({"authority_hints": [ta.id], "metadata": {"federation_entity": {}}}),
+        ),
+    ] {
+        store.insert(&token).unwrap();
+    }
+    let anchor = TrustAnchor {
+        entity_id: ta.id.to_string(),
+        jwks: ta.jwks(),
+    };
+    let tokens = [
+        rp_configuration.as_str(),
+        about_rp.as_str(),
+        ta_configuration.as_str(),
+    ];
+    let mut chain = validate_trust_chain(&tokens, &anchor, NOW).unwrap();
+    assert_eq!(chain.trust_marks, None);
+    chain.check_trust_marks(&store, NOW);
+
+    let results: Vec<Result<Option<String>, String>> = chain
+        .trust_marks
+        .iter()
+        .flatten()
+        .map(|o| o.result.clone().map(|t| t.delegated_by))
+        .collect();
+    assert_eq!(
+        results,
+        [
+            Ok(None),
+            Err("Trust mark error: the trust mark has expired".to_string()),
+            Err(
+                "Trust mark error: https://rp.example.org may not issue https://ta.example.org/certified"
+                    .to_string()
+            ),
+            Ok(Some(owner.id.to_string())),
+            Err(
+                "Trust mark error: the delegation is for https://rp.example.org to issue https://ta.example.org/owned"
+                    .to_string()
+            ),
+            Err(
+                "Trust mark error: https://ta.example.org does not recognise https://unknown.example.org"
+                    .to_string()
+            ),
+        ]
+    );
+
+    // Only the valid trust marks go in a resolve response
+    let response =
+        ResolveResponse::from_chain("https://resolver.example.org", &chain, NOW).unwrap();
+    assert_eq!(
+        response.trust_marks,
+        Some(vec![trust_marks[0].clone(), trust_marks[3].clone()])
+    );
+}
+
+// A trust anchor which allows tmi to issue CERTIFIED, and anyone delegated by owner to
+// issue OWNED. The leaf rp is directly below the trust anchor, and so is tmi.
+struct Federation {
+    ta: Entity,
+    tmi: Entity,
+    owner: Entity,
+    rp: Entity,
+    store: MemoryStore,
+}
+
+impl Federation {
+    fn new() -> Federation {
+        let federation = Federation {
+            ta: Entity {
+                id: "https://ta.example.org",
+                key: TestKey::ed(40),
+                alg: "EdDSA",
+            },
+            tmi: Entity {
+                id: "https://tmi.example.org",
+                key: TestKey::ec(41),
+                alg: "ES256",
+            },
+            owner: Entity {
+                id: "https://owner.example.org",
+                key: TestKey::ed(42),
+                alg: "EdDSA",
+            },
+            rp: Entity {
+                id: "https://rp.example.org",
+                key: TestKey::ed(43),
+                alg: "EdDSA",
+            },
+            store: MemoryStore::new(),
+        };
+        let mut store = MemoryStore::new();
+        for token in [
+            federation.ta_configuration(),
+            federation
+                .ta
+                .statement(&federation.tmi, 1700086400, json!({})),
+            federation.tmi.statement(
+                &federation.tmi,
+                1700086400,
+                json!({"authority_hints": [federation.ta.id], "metadata": {"federation_entity": {}}}),
+            ),
+        ] {
+            store.insert(&token).unwrap();
+        }
+        Federation {
+            store,
+            ..federation
+        }
+    }
+
+    fn ta_configuration(&self) -> String {
+        self.ta.statement(
+            &self.ta,
+            1700086400,
+            json!({
+                "trust_mark_issuers": {CERTIFIED: [self.tmi.id], OWNED: []},
+                "trust_mark_owners": {OWNED: {"sub": self.owner.id, "jwks": self.owner.jwks()}},
+            }),
+        )
+    }
+
+    // A trust_marks entry with a trust mark by issuer about rp, the claims replace the
+    // default ones
+    fn trust_mark(&self, issuer: &Entity, claims: Value) -> Value {
+        let mut all = json!({"iss": issuer.id, "sub": self.rp.id, "iat": 1700000000});
+        for (name, value) in claims.as_object().unwrap().iter() {
+            all[name] = value.clone();
+        }
+        let token = sign_jwt(&issuer.key, issuer.alg, issuer.id, "trust-mark+jwt", &all);
+        json!({"trust_mark_type": all["trust_mark_type"], "trust_mark": token})
+    }
+
+    // A delegation of OWNED to the entity, signed by signer
+    fn delegation(&self, signer: &Entity, to: &Entity, claims: Value) -> String {
+        let mut all = json!({
+            "iss": self.owner.id,
+            "sub": to.id,
+            "trust_mark_type": OWNED,
+            "iat": 1700000000,
+        });
+        for (name, value) in claims.as_object().unwrap().iter() {
+            all[name] = value.clone();
+        }
+        sign_jwt(
+            &signer.key,
+            signer.alg,
+            signer.id,
+            "trust-mark-delegation+jwt",
+            &all,
+        )
+    }
+
+    // The chain of rp with these trust marks, not checked yet
+    fn chain(&self, trust_marks: &[Value]) -> ValidatedChain {
+        let rp_configuration = self.rp.statement(
+            &self.rp,
+            1700086400,
+            json!({
+                "authority_hints": [self.ta.id],
+                "metadata": {"openid_relying_party": {"client_name": "RP"}},
+                "trust_marks": trust_marks,
+            }),
+        );
+        let about_rp = self.ta.statement(&self.rp, 1700086400, json!({}));
+        let ta_configuration = self.ta_configuration();
+        let anchor = TrustAnchor {
+            entity_id: self.ta.id.to_string(),
+            jwks: self.ta.jwks(),
+        };
+        let tokens = [
+            rp_configuration.as_str(),
+            about_rp.as_str(),
+            ta_configuration.as_str(),
+        ];
+        validate_trust_chain(&tokens, &anchor, NOW).unwrap()
+    }
+
+    // The outcome of checking the one trust mark
+    fn check(&self, trust_mark: Value) -> Result<Option<String>, String> {
+        let mut chain = self.chain(&[trust_mark]);
+        chain.check_trust_marks(&self.store, NOW);
+        let outcomes = chain.trust_marks.unwrap();
+        assert_eq!(outcomes.len(), 1);
+        outcomes[0].result.clone().map(|t| t.delegated_by)
+    }
+}
+
+#[test]
+fn accepts_a_delegated_issuer() {
+    let f = Federation::new();
+    let delegation = f.delegation(&f.owner, &f.tmi, json!({"exp": 1700086400}));
+    let trust_mark = f.trust_mark(
+        &f.tmi,
+        json!({"trust_mark_type": OWNED, "delegation": delegation}),
+    );
+    assert_eq!(f.check(trust_mark), Ok(Some(f.owner.id.to_string())));
+}
+
+#[test]
+fn rejects_bad_delegations() {
+    let f = Federation::new();
+    let owned = |delegation: Option<String>| {
+        let mut claims = json!({"trust_mark_type": OWNED});
+        if let Some(delegation) = delegation {
+            claims["delegation"] = json!(delegation);
+        }
+        f.check(f.trust_mark(&f.tmi, claims)).unwrap_err()
+    };
+    assert_eq!(
+        owned(None),
+        "Trust mark error: https://ta.example.org/owned has an owner, but the trust mark has no delegation"
+    );
+    // Signed by the issuer itself instead of the owner
+    assert_eq!(
+        owned(Some(f.delegation(&f.tmi, &f.tmi, json!({})))),
+        "Trust mark error: the delegation of https://owner.example.org is not valid: JWS error: no key with kid https://tmi.example.org"
+    );
+    assert_eq!(
+        owned(Some(f.delegation(
+            &f.owner,
+            &f.tmi,
+            json!({"exp": 1700000050})
+        ))),
+        "Trust mark error: the delegation has expired"
+    );
+    assert_eq!(
+        owned(Some(f.delegation(&f.owner, &f.rp, json!({})))),
+        "Trust mark error: the delegation is for https://rp.example.org to issue https://ta.example.org/owned"
+    );
+    assert_eq!(
+        owned(Some(f.delegation(
+            &f.owner,
+            &f.tmi,
+            json!({"trust_mark_type": CERTIFIED})
+        ))),
+        "Trust mark error: the delegation is for https://tmi.example.org to issue https://ta.example.org/certified"
+    );
+}
+
+#[test]
+fn rejects_an_expired_trust_mark() {
+    let f = Federation::new();
+    let trust_mark = f.trust_mark(&f.tmi, json!({"trust_mark_type": CERTIFIED, "exp": NOW}));
+    assert_eq!(
+        f.check(trust_mark),
+        Err("Trust mark error: the trust mark has expired".to_string())
+    );
+    let trust_mark = f.trust_mark(
+        &f.tmi,
+        json!({"trust_mark_type": CERTIFIED, "exp": NOW + 1}),
+    );
+    assert_eq!(f.check(trust_mark), Ok(None));
+}
+
+#[test]
+fn rejects_a_trust_mark_about_another_entity() {
+    let f = Federation::new();
+    let trust_mark = f.trust_mark(
+        &f.tmi,
+        json!({"trust_mark_type": CERTIFIED, "sub": "https://op.example.org"}),
+    );
+    assert_eq!(
+        f.check(trust_mark),
+        Err(
+            "Trust mark error: the trust mark is about https://op.example.org, not https://rp.example.org"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn rejects_an_issuer_not_in_trust_mark_issuers() {
+    let f = Federation::new();
+    let trust_mark = f.trust_mark(&f.owner, json!({"trust_mark_type": CERTIFIED}));
+    assert_eq!(
+        f.check(trust_mark),
+        Err(
+            "Trust mark error: https://owner.example.org may not issue https://ta.example.org/certified"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn unchecked_trust_marks_are_not_in_resolve_responses() {
+    let f = Federation::new();
+    let chain = f.chain(&[f.trust_mark(&f.tmi, json!({"trust_mark_type": CERTIFIED}))]);
+    let response =
+        ResolveResponse::from_chain("https://resolver.example.org", &chain, NOW).unwrap();
+    assert_eq!(response.trust_marks, None);
+    assert!(response.to_claims().get("trust_marks").is_none());
+}
+
+// Counts the statements asked for
+struct CountingStore<'a> {
+    store: &'a MemoryStore,
+    requests: RefCell<Vec<(String, String)>>,
+}
+
+impl StatementStore for CountingStore<'_> {
+    fn statement(&self, issuer: &str, subject: &str) -> anyhow::Result<Option<String>> {
+        self.requests
+            .borrow_mut()
+            .push((issuer.to_string(), subject.to_string()));
+        self.store.statement(issuer, subject)
+    }
+}
+
+#[test]
+fn builds_the_chain_of_an_issuer_once() {
+    let f = Federation::new();
+    let store = CountingStore {
+        store: &f.store,
+        requests: RefCell::new(Vec::new()),
+    };
+    let trust_marks = [
+        f.trust_mark(&f.tmi, json!({"trust_mark_type": CERTIFIED})),
+        f.trust_mark(
+            &f.tmi,
+            json!({"trust_mark_type": CERTIFIED, "exp": 1700086400}),
+        ),
+    ];
+    let checker = TrustMarkChecker::new(&store, NOW);
+    for _ in 0..2 {
+        let mut chain = f.chain(&trust_marks);
+        chain.check_trust_marks_with(&checker);
+        assert_eq!(chain.valid_trust_marks().len(), 2);
+    }
+    let tmi_configuration = (f.tmi.id.to_string(), f.tmi.id.to_string());
+    let requests = store.requests.borrow();
+    assert_eq!(
+        requests.iter().filter(|r| **r == tmi_configuration).count(),
+        1
+    );
+}
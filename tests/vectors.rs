@@ -0,0 +1,54 @@
+// Every metadata policy test vector becomes its own named test case.
+//
+// The hand-written vectors in tests/data are always run, they are in the connect2id
+// format but are not taken from its file and their expected results come from this
+// implementation, so they only catch regressions. The full connect2id file checks
+// conformance, it is run when it is found at $OIDFED_TEST_VECTORS, or else in the
+// default data/ location.
+use libtest_mimic::{Arguments, Failed, Trial};
+use oidfed_metadata_policy::conformance::{check_test_vector, load_test_vectors, vector_number};
+
+use std::path::{Path, PathBuf};
+
+const HAND_WRITTEN_VECTORS: &str = "tests/data/hand-written-policy-vectors.json";
+const DEFAULT_VECTORS: &str = "data/metadata-policy-test-vectors-2025-02-13.json";
+
+fn trials_from(prefix: &str, path: &Path) -> Vec<Trial> {
+    let vectors = load_test_vectors(path).unwrap();
+    vectors
+        .into_iter()
+        .enumerate()
+        .map(|(index, vector)| {
+            let n = vector_number(&vector).unwrap_or(index as i64 + 1);
+            let kind = if vector.get("error").is_some() {
+                "error"
+            } else {
+                "ok"
+            };
+            Trial::test(format!("{}::vector_{:04}_{}", prefix, n, kind), move || {
+                check_test_vector(&vector).map_err(Failed::from)
+            })
+        })
+        .collect()
+}
+
+fn main() {
+    let args = Arguments::from_args();
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    let mut trials = trials_from("hand_written", &root.join(HAND_WRITTEN_VECTORS));
+
+    let full = match std::env::var_os("OIDFED_TEST_VECTORS") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(root.join(DEFAULT_VECTORS)).filter(|p| p.exists()),
+    };
+    match full {
+        Some(path) => trials.extend(trials_from("connect2id", &path)),
+        None => eprintln!(
+            "Full test vectors not found, set OIDFED_TEST_VECTORS or put them in {}",
+            DEFAULT_VECTORS
+        ),
+    }
+
+    libtest_mimic::run(&args, trials).exit();
+}